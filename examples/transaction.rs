@@ -38,6 +38,11 @@ fn get(client: &Client, key: &Key) -> Value {
     txn.get(key).wait().expect("Could not get value")
 }
 
+fn batch_get(client: &Client, keys: &[Key]) -> Vec<KvPair> {
+    let txn = client.begin();
+    txn.batch_get(keys).wait().expect("Could not get values")
+}
+
 fn scan(client: &Client, range: impl RangeBounds<Key>, mut limit: usize) {
     client
         .begin()
@@ -89,6 +94,12 @@ fn main() {
     let value1 = get(&txn, &key1);
     println!("{:?}", (key1, value1));
 
+    // batch get
+    let key1: Key = b"key1".to_vec().into();
+    let key2: Key = b"key2".to_vec().into();
+    let pairs = batch_get(&txn, &[key1, key2]);
+    println!("{:?}", pairs);
+
     // scan
     let key1: Key = b"key1".to_vec().into();
     scan(&txn, key1.., 10);
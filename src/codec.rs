@@ -0,0 +1,254 @@
+// Copyright 2018 The TiKV Project Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! TiDB's memcomparable key encoding, for reading and writing keys of TiDB-owned tables.
+
+use {Error, Key, Result};
+
+const SIGN_MASK: u64 = 0x8000_0000_0000_0000;
+const ENC_GROUP_SIZE: usize = 8;
+const ENC_MARKER: u8 = 0xFF;
+const ENC_PAD: u8 = 0x0;
+
+const BYTES_FLAG: u8 = 1;
+const INT_FLAG: u8 = 3;
+const UINT_FLAG: u8 = 4;
+
+/// Appends `v` so that encoded integers compare in numeric order.
+pub fn encode_int(buf: &mut Vec<u8>, v: i64) {
+    encode_uint(buf, v as u64 ^ SIGN_MASK);
+}
+
+pub fn decode_int(data: &[u8]) -> Result<(i64, &[u8])> {
+    let (v, rest) = decode_uint(data)?;
+    Ok(((v ^ SIGN_MASK) as i64, rest))
+}
+
+pub fn encode_uint(buf: &mut Vec<u8>, v: u64) {
+    for i in (0..8).rev() {
+        buf.push((v >> (i * 8)) as u8);
+    }
+}
+
+pub fn decode_uint(data: &[u8]) -> Result<(u64, &[u8])> {
+    if data.len() < 8 {
+        return Err(Error::Codec(format!(
+            "insufficient bytes to decode an integer: {}",
+            data.len()
+        )));
+    }
+    let v = data[..8].iter().fold(0, |v, b| v << 8 | u64::from(*b));
+    Ok((v, &data[8..]))
+}
+
+/// Appends `data` in groups of 8 bytes, each padded with zeros and followed by a
+/// marker byte recording the amount of padding.
+pub fn encode_bytes(buf: &mut Vec<u8>, data: &[u8]) {
+    let len = data.len();
+    let mut index = 0;
+    while index <= len {
+        let remain = len - index;
+        let pad = if remain >= ENC_GROUP_SIZE {
+            buf.extend_from_slice(&data[index..index + ENC_GROUP_SIZE]);
+            0
+        } else {
+            buf.extend_from_slice(&data[index..]);
+            let pad = ENC_GROUP_SIZE - remain;
+            let padded = buf.len() + pad;
+            buf.resize(padded, ENC_PAD);
+            pad
+        };
+        buf.push(ENC_MARKER - pad as u8);
+        index += ENC_GROUP_SIZE;
+    }
+}
+
+pub fn decode_bytes(mut data: &[u8]) -> Result<(Vec<u8>, &[u8])> {
+    let mut decoded = Vec::with_capacity(data.len() / (ENC_GROUP_SIZE + 1) * ENC_GROUP_SIZE);
+    loop {
+        if data.len() < ENC_GROUP_SIZE + 1 {
+            return Err(Error::Codec(
+                "insufficient bytes to decode bytes".to_owned(),
+            ));
+        }
+        let (group, rest) = data.split_at(ENC_GROUP_SIZE + 1);
+        data = rest;
+        let pad = (ENC_MARKER - group[ENC_GROUP_SIZE]) as usize;
+        if pad > ENC_GROUP_SIZE {
+            return Err(Error::Codec(format!(
+                "invalid marker byte {:?}",
+                group[ENC_GROUP_SIZE]
+            )));
+        }
+        let real_size = ENC_GROUP_SIZE - pad;
+        decoded.extend_from_slice(&group[..real_size]);
+        if pad != 0 {
+            if group[real_size..ENC_GROUP_SIZE]
+                .iter()
+                .any(|b| *b != ENC_PAD)
+            {
+                return Err(Error::Codec("invalid padding bytes".to_owned()));
+            }
+            return Ok((decoded, data));
+        }
+    }
+}
+
+/// A value that can be part of a multi-column key. Each value is prefixed with
+/// its type flag, as TiDB does for index keys.
+pub trait EncodeKey {
+    fn encode_key(&self, buf: &mut Vec<u8>);
+}
+
+impl EncodeKey for i64 {
+    fn encode_key(&self, buf: &mut Vec<u8>) {
+        buf.push(INT_FLAG);
+        encode_int(buf, *self);
+    }
+}
+
+impl EncodeKey for u64 {
+    fn encode_key(&self, buf: &mut Vec<u8>) {
+        buf.push(UINT_FLAG);
+        encode_uint(buf, *self);
+    }
+}
+
+impl EncodeKey for &[u8] {
+    fn encode_key(&self, buf: &mut Vec<u8>) {
+        buf.push(BYTES_FLAG);
+        encode_bytes(buf, self);
+    }
+}
+
+impl EncodeKey for Vec<u8> {
+    fn encode_key(&self, buf: &mut Vec<u8>) {
+        self.as_slice().encode_key(buf);
+    }
+}
+
+impl EncodeKey for &str {
+    fn encode_key(&self, buf: &mut Vec<u8>) {
+        self.as_bytes().encode_key(buf);
+    }
+}
+
+impl EncodeKey for String {
+    fn encode_key(&self, buf: &mut Vec<u8>) {
+        self.as_bytes().encode_key(buf);
+    }
+}
+
+macro_rules! impl_encode_key_for_tuple {
+    ($($name:ident)+) => {
+        impl<$($name: EncodeKey),+> EncodeKey for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn encode_key(&self, buf: &mut Vec<u8>) {
+                let ($(ref $name,)+) = *self;
+                $($name.encode_key(buf);)+
+            }
+        }
+    };
+}
+
+impl_encode_key_for_tuple!(A);
+impl_encode_key_for_tuple!(A B);
+impl_encode_key_for_tuple!(A B C);
+impl_encode_key_for_tuple!(A B C D);
+impl_encode_key_for_tuple!(A B C D E);
+impl_encode_key_for_tuple!(A B C D E F);
+
+/// Builds a `Key` from a typed tuple, e.g. `encode_key((table_id, "name"))`.
+pub fn encode_key(values: impl EncodeKey) -> Key {
+    let mut buf = Vec::new();
+    values.encode_key(&mut buf);
+    buf.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded_bytes(data: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        encode_bytes(&mut buf, data);
+        buf
+    }
+
+    fn encoded_int(v: i64) -> Vec<u8> {
+        let mut buf = Vec::new();
+        encode_int(&mut buf, v);
+        buf
+    }
+
+    #[test]
+    fn bytes_are_padded_to_groups() {
+        assert_eq!(encoded_bytes(b""), vec![0, 0, 0, 0, 0, 0, 0, 0, 0xF7]);
+        let mut eight = b"12345678".to_vec();
+        eight.extend_from_slice(&[0xFF, 0, 0, 0, 0, 0, 0, 0, 0, 0xF7]);
+        assert_eq!(encoded_bytes(b"12345678"), eight);
+        let mut nine = b"12345678".to_vec();
+        nine.extend_from_slice(&[0xFF, b'9', 0, 0, 0, 0, 0, 0, 0, 0xF8]);
+        assert_eq!(encoded_bytes(b"123456789"), nine);
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        for data in &[&b""[..], b"1234567", b"12345678", b"123456789", &[0; 16]] {
+            let mut buf = encoded_bytes(data);
+            buf.extend_from_slice(b"rest");
+            let (decoded, rest) = decode_bytes(&buf).unwrap();
+            assert_eq!(decoded, *data);
+            assert_eq!(rest, b"rest");
+        }
+        assert!(decode_bytes(&[0; 8]).is_err());
+        assert!(decode_bytes(&[1, 0, 0, 0, 0, 0, 0, 0, 0xF7]).is_err());
+        assert!(decode_bytes(&[0, 0, 0, 0, 0, 0, 0, 0, 0xF7]).is_ok());
+        assert!(decode_bytes(&[0, 0, 0, 0, 0, 0, 0, 0, 0xF6]).is_err());
+    }
+
+    #[test]
+    fn bytes_keep_their_order() {
+        let data: Vec<&[u8]> = vec![
+            b"",
+            b"\0",
+            b"a",
+            b"a\0",
+            b"abcdefgh",
+            b"abcdefgh\0",
+            b"a\xff",
+            b"b",
+        ];
+        for pair in data.windows(2) {
+            assert!(
+                encoded_bytes(pair[0]) < encoded_bytes(pair[1]),
+                "{:?}",
+                pair
+            );
+        }
+    }
+
+    #[test]
+    fn ints_round_trip_and_keep_their_order() {
+        let ints = [i64::MIN, -256, -1, 0, 1, 255, i64::MAX];
+        for v in &ints {
+            let buf = encoded_int(*v);
+            assert_eq!(buf.len(), 8);
+            assert_eq!(decode_int(&buf).unwrap(), (*v, &[][..]));
+        }
+        for pair in ints.windows(2) {
+            assert!(encoded_int(pair[0]) < encoded_int(pair[1]), "{:?}", pair);
+        }
+        assert!(decode_int(&[0; 7]).is_err());
+    }
+}
@@ -0,0 +1,62 @@
+// Copyright 2018 The TiKV Project Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Adapters running the request futures as `std::future::Future`s, so they can be
+//! `.await`ed from `async fn`s.
+
+use std::future::Future as StdFuture;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{self, Context, Waker};
+
+use futures::executor::{self, Notify, Spawn};
+use futures::{Async, Future};
+
+struct WakerNotify(Waker);
+
+impl Notify for WakerNotify {
+    fn notify(&self, _id: usize) {
+        self.0.wake_by_ref();
+    }
+}
+
+pub struct Compat<F> {
+    inner: Spawn<F>,
+}
+
+impl<F> StdFuture for Compat<F>
+where
+    F: Future + Unpin,
+{
+    type Output = Result<F::Item, F::Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> task::Poll<Self::Output> {
+        let notify = Arc::new(WakerNotify(cx.waker().clone()));
+        match self.inner.poll_future_notify(&notify, 0) {
+            Ok(Async::Ready(item)) => task::Poll::Ready(Ok(item)),
+            Ok(Async::NotReady) => task::Poll::Pending,
+            Err(e) => task::Poll::Ready(Err(e)),
+        }
+    }
+}
+
+/// Adds `compat` to every request future, e.g. `client.get(&key).compat().await`.
+pub trait FutureExt: Future + Sized {
+    fn compat(self) -> Compat<Self> {
+        Compat {
+            inner: executor::spawn(self),
+        }
+    }
+}
+
+impl<F: Future> FutureExt for F {}
@@ -0,0 +1,59 @@
+// Copyright 2018 The TiKV Project Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Expands the `pd+srv://` endpoints described on `Config::new` while connecting.
+
+#[cfg(feature = "dns-srv")]
+use trust_dns_resolver::Resolver;
+
+const SRV_SCHEME: &str = "pd+srv://";
+const DEFAULT_PD_PORT: u16 = 2379;
+
+/// Replaces each `pd+srv://name` endpoint with the `host:port` targets of the SRV
+/// records of `name`, or with `name` itself if the lookup fails or finds nothing.
+pub fn resolve_endpoints(endpoints: &[String]) -> Vec<String> {
+    let mut resolved = Vec::with_capacity(endpoints.len());
+    for endpoint in endpoints {
+        if !endpoint.starts_with(SRV_SCHEME) {
+            resolved.push(endpoint.clone());
+            continue;
+        }
+        let name = &endpoint[SRV_SCHEME.len()..];
+        match lookup_srv(name) {
+            Some(ref targets) if !targets.is_empty() => resolved.extend_from_slice(targets),
+            _ if name.contains(':') => resolved.push(name.to_owned()),
+            _ => resolved.push(format!("{}:{}", name, DEFAULT_PD_PORT)),
+        }
+    }
+    resolved
+}
+
+#[cfg(feature = "dns-srv")]
+fn lookup_srv(name: &str) -> Option<Vec<String>> {
+    let resolver = Resolver::from_system_conf().ok()?;
+    let lookup = resolver.lookup_srv(name).ok()?;
+    Some(
+        lookup
+            .iter()
+            .map(|srv| {
+                let target = srv.target().to_utf8();
+                format!("{}:{}", target.trim_end_matches('.'), srv.port())
+            })
+            .collect(),
+    )
+}
+
+#[cfg(not(feature = "dns-srv"))]
+fn lookup_srv(_name: &str) -> Option<Vec<String>> {
+    None
+}
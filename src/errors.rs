@@ -13,64 +13,238 @@
 
 use std::error;
 use std::result;
+use std::sync::Arc;
+
+use Key;
 
 quick_error!{
     #[derive(Debug)]
     pub enum Error {
         Io(err: ::std::io::Error) {
             from()
-            cause(err)
-            description(err.description())
+            source(err)
+            display("IO error: {}", err)
         }
         Grpc(err: ::grpc::Error) {
             from()
-            cause(err)
-            description(err.description())
+            source(err)
+            display("gRPC error: {}", err)
         }
         Canceled(err: ::futures::sync::oneshot::Canceled) {
             from()
-            cause(err)
-            description(err.description())
+            source(err)
+            display("request canceled: {}", err)
+        }
+        InvalidConfig(err: ::toml::de::Error) {
+            from()
+            source(err)
+            display("invalid config: {}", err)
         }
         Other(err: Box<error::Error + Sync + Send>) {
             from()
-            cause(err.as_ref())
-            description(err.description())
-            display("unknown error {:?}", err)
+            source(err.as_ref())
+            display("unknown error: {}", err)
         }
         RegionForKeyNotFound(key: Vec<u8>) {
-            description("region is not found")
             display("region is not found for key {:?}", key)
         }
         RegionNotFound(id: u64) {
-            description("region is not found")
             display("region {:?} is not found", id)
         }
         NotLeader(region_id: u64) {
-            description("peer is not leader")
             display("peer is not leader for region {:?}.", region_id)
         }
         StoreNotMatch {
-            description("store not match")
             display("store not match")
         }
         KeyNotInRegion(key: Vec<u8>, region_id: u64, start_key: Vec<u8>, end_key: Vec<u8>) {
-            description("region is not found")
             display("key {:?} is not in region {:?}: [{:?}, {:?})", key, region_id, start_key, end_key)
         }
         StaleEpoch {
-            description("stale epoch")
             display("stale epoch")
         }
         ServerIsBusy(reason: String) {
-            description("server is busy")
             display("server is busy: {:?}", reason)
         }
         RaftEntryTooLarge(region_id: u64, entry_size: u64) {
-            description("raft entry too large")
             display("{:?} bytes raft entry of region {:?} is too large", entry_size, region_id)
         }
+        ConnectFailed(endpoints: Vec<String>) {
+            display("failed to connect to any PD endpoint in {:?}", endpoints)
+        }
+        Security(reason: String) {
+            display("security error: {}", reason)
+        }
+        Timeout {
+            display("operation timed out")
+        }
+        RegionError(message: String) {
+            display("region error: {:?}", message)
+        }
+        /// A key error reported by TiKV, with the key it concerns. Batch requests fail
+        /// with the first key rejected.
+        KeyError { key: Key, reason: String } {
+            display("key error on {:?}: {}", key, reason)
+        }
+        CasUnsupported {
+            display("compare and swap requires api-version 2 on the TiKV cluster")
+        }
+        UnknownColumnFamily(cf: String) {
+            display("unknown column family {:?}", cf)
+        }
+        KeyTooLong { len: usize, max: usize } {
+            display("key length {:?} exceeds the limit {:?}", len, max)
+        }
+        ValueTooLarge { index: Option<usize>, len: usize, max: usize } {
+            display("value length {:?}{} exceeds the limit {:?}", len,
+                index.map(|i| format!(" of pair {}", i)).unwrap_or_default(), max)
+        }
+        Closed {
+            display("the client has been closed")
+        }
+        KeyspaceNotFound(name: String) {
+            display("keyspace {:?} does not exist", name)
+        }
+        Unhealthy {
+            display("the TiKV cluster is not serving")
+        }
+        StoreNotFound(store_id: u64) {
+            display("store {} does not exist", store_id)
+        }
+        Codec(reason: String) {
+            display("codec error: {}", reason)
+        }
+        /// The error of a request whose result was shared with other callers, e.g. by
+        /// `Config::coalesce_reads`, which all see this same error.
+        Shared(err: Arc<Error>) {
+            source(&**err)
+            display("{}", err)
+        }
+        WriteInDoubt {
+            display("an earlier write with the same idempotency key may have been applied")
+        }
+        /// The error returned by the `Scan::map_value` transform for the value of `key`.
+        MapValue { key: Key, err: Box<Error> } {
+            source(&**err)
+            display("failed to map the value of {:?}: {}", key, err)
+        }
+        ScanLimitExceeded { limit: u32, max: u32 } {
+            display("scan limit {} exceeds the configured maximum {}", limit, max)
+        }
+        /// A request that needs PD or the region layout, sent to `Client::new_mock`.
+        MockUnsupported(request: &'static str) {
+            display("{} is not supported by the mock client", request)
+        }
     }
 }
 
 pub type Result<T> = result::Result<T, Error>;
+
+impl Error {
+    /// Whether the failed request may succeed if sent again, possibly after a backoff.
+    ///
+    /// Retryable: gRPC `Unavailable` and `DeadlineExceeded`, `ServerIsBusy`, `Timeout`,
+    /// and the region errors `NotLeader`, `StaleEpoch`, `StoreNotMatch`,
+    /// `RegionNotFound`, `RegionForKeyNotFound` and `RegionError`, which are resolved
+    /// by refreshing the region from PD.
+    ///
+    /// Everything else is permanent, e.g. `KeyTooLong`, `ValueTooLarge`, `ScanLimitExceeded`,
+    /// `UnknownColumnFamily`, `Security`, `InvalidConfig` and gRPC
+    /// `Unauthenticated` or `PermissionDenied`.
+    pub fn is_retryable(&self) -> bool {
+        match *self {
+            Error::Shared(ref err) => err.is_retryable(),
+            Error::Grpc(_) => matches!(
+                self.grpc_status().map(|status| status.status),
+                Some(::grpc::RpcStatusCode::Unavailable)
+                    | Some(::grpc::RpcStatusCode::DeadlineExceeded)
+            ),
+            Error::ServerIsBusy(_)
+            | Error::Timeout
+            | Error::NotLeader(_)
+            | Error::StaleEpoch
+            | Error::StoreNotMatch
+            | Error::RegionNotFound(_)
+            | Error::RegionForKeyNotFound(_)
+            | Error::RegionError(_) => true,
+            _ => false,
+        }
+    }
+
+    /// The status TiKV or PD answered a failed gRPC call with, carrying its code, e.g.
+    /// `ResourceExhausted`, and details.
+    pub fn grpc_status(&self) -> Option<&::grpc::RpcStatus> {
+        match *self {
+            Error::Shared(ref err) => err.grpc_status(),
+            Error::Grpc(::grpc::Error::RpcFailure(ref status))
+            | Error::Grpc(::grpc::Error::RpcFinished(Some(ref status))) => Some(status),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error;
+    use std::sync::Arc;
+
+    use grpc::{self, RpcStatus, RpcStatusCode};
+
+    use super::Error;
+
+    fn rpc_failure(code: RpcStatusCode) -> Error {
+        Error::Grpc(grpc::Error::RpcFailure(RpcStatus::new(code, None)))
+    }
+
+    #[test]
+    fn grpc_errors_are_retryable_by_code() {
+        assert!(rpc_failure(RpcStatusCode::Unavailable).is_retryable());
+        assert!(rpc_failure(RpcStatusCode::DeadlineExceeded).is_retryable());
+        assert!(!rpc_failure(RpcStatusCode::PermissionDenied).is_retryable());
+        let shared = Error::Shared(Arc::new(rpc_failure(RpcStatusCode::Unavailable)));
+        assert!(shared.is_retryable());
+        let shared = Error::Shared(Arc::new(rpc_failure(RpcStatusCode::PermissionDenied)));
+        assert!(!shared.is_retryable());
+    }
+
+    #[test]
+    fn grpc_status() {
+        let code = |err: &Error| err.grpc_status().map(|status| status.status);
+        let err = rpc_failure(RpcStatusCode::PermissionDenied);
+        assert_eq!(code(&err), Some(RpcStatusCode::PermissionDenied));
+        let shared = Error::Shared(Arc::new(rpc_failure(RpcStatusCode::Unavailable)));
+        assert_eq!(code(&shared), Some(RpcStatusCode::Unavailable));
+        let status = RpcStatus::new(RpcStatusCode::DeadlineExceeded, None);
+        let finished = Error::Grpc(grpc::Error::RpcFinished(Some(status)));
+        assert_eq!(code(&finished), Some(RpcStatusCode::DeadlineExceeded));
+        let finished = Error::Grpc(grpc::Error::RpcFinished(None));
+        assert!(finished.grpc_status().is_none());
+        assert!(Error::Timeout.grpc_status().is_none());
+    }
+
+    #[test]
+    fn resource_exhausted_status_is_preserved() {
+        let details = Some("grpc: received message larger than max".to_owned());
+        let status = RpcStatus::new(RpcStatusCode::ResourceExhausted, details.clone());
+        let err: Error = grpc::Error::RpcFailure(status).into();
+        let status = err.grpc_status().unwrap();
+        assert_eq!(status.status, RpcStatusCode::ResourceExhausted);
+        assert_eq!(status.details, details);
+        assert!(!err.is_retryable());
+    }
+
+    #[test]
+    fn source_downcasts_to_the_grpc_error() {
+        let status = |err: &(error::Error + 'static)| match err.downcast_ref::<grpc::Error>() {
+            Some(grpc::Error::RpcFailure(status)) => status.status,
+            _ => panic!("unexpected source {:?}", err),
+        };
+        let err = rpc_failure(RpcStatusCode::Unavailable);
+        let source = error::Error::source(&err).unwrap();
+        assert_eq!(status(source), RpcStatusCode::Unavailable);
+        let shared = Error::Shared(Arc::new(err));
+        let source = error::Error::source(&shared).unwrap();
+        assert!(source.downcast_ref::<Error>().is_some());
+        assert_eq!(status(source.source().unwrap()), RpcStatusCode::Unavailable);
+    }
+}
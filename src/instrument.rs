@@ -0,0 +1,122 @@
+// Copyright 2018 The TiKV Project Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// The metrics and the tracing span of a request future. Without the `metrics` and
+// `tracing` features `Instrument` is empty and its methods do nothing.
+
+use futures::{Async, Poll};
+
+#[cfg(feature = "metrics")]
+use metrics::RequestStats;
+use raw::ColumnFamily;
+#[cfg(feature = "tracing")]
+use trace;
+#[cfg(feature = "tracing")]
+use tracing::span::{EnteredSpan, Span};
+use {Error, Peer, Region};
+
+#[derive(Default)]
+pub struct Instrument {
+    #[cfg(feature = "metrics")]
+    stats: Option<RequestStats>,
+    #[cfg(feature = "tracing")]
+    span: Option<Span>,
+}
+
+// Keeps the request span entered while the future polls.
+pub struct Entered {
+    #[cfg(feature = "tracing")]
+    _span: EnteredSpan,
+}
+
+impl Instrument {
+    /// Starts measuring the request and opens its span on the first poll, and enters
+    /// the span until the returned guard is dropped.
+    pub fn enter(
+        &mut self,
+        op: &'static str,
+        key_len: usize,
+        cf: &Option<ColumnFamily>,
+    ) -> Entered {
+        let cf = cf.as_ref().map_or("default", ColumnFamily::as_str);
+        #[cfg(feature = "metrics")]
+        {
+            if self.stats.is_none() {
+                self.stats = Some(RequestStats::new(op, cf));
+            }
+        }
+        #[cfg(feature = "tracing")]
+        {
+            let span = self
+                .span
+                .get_or_insert_with(|| trace::request_span(op, key_len, cf));
+            Entered {
+                _span: span.clone().entered(),
+            }
+        }
+        #[cfg(not(feature = "tracing"))]
+        {
+            let _ = (op, key_len, cf);
+            Entered {}
+        }
+    }
+
+    /// Records the region the request was routed to, and the store of its leader.
+    pub fn route(&self, region: &Region) {
+        #[cfg(feature = "tracing")]
+        {
+            if let Some(ref span) = self.span {
+                trace::record_region(span, region.id);
+            }
+        }
+        if let Some(peer) = region.leader.as_ref().or_else(|| region.peers.first()) {
+            self.route_store(peer);
+        }
+    }
+
+    /// Records the store the request was sent to. A request sent to several stores,
+    /// or retried against another one, is attributed to the last.
+    pub fn route_store(&self, peer: &Peer) {
+        #[cfg(feature = "metrics")]
+        {
+            if let Some(ref stats) = self.stats {
+                stats.store(peer.store_id);
+            }
+        }
+        #[cfg(feature = "tracing")]
+        {
+            if let Some(ref span) = self.span {
+                trace::record_store(span, &peer.store_address);
+            }
+        }
+        let _ = peer;
+    }
+
+    /// Records the outcome once the request resolves, and closes its span.
+    pub fn done<T>(&mut self, result: Poll<T, Error>) -> Poll<T, Error> {
+        if let Ok(Async::NotReady) = result {
+            return result;
+        }
+        #[cfg(feature = "metrics")]
+        {
+            if let Some(stats) = self.stats.take() {
+                stats.done(&result);
+            }
+        }
+        #[cfg(feature = "tracing")]
+        {
+            self.span = None;
+        }
+        result
+    }
+}
@@ -11,6 +11,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+// `quick_error!` recurses once per variant of `Error`.
+#![recursion_limit = "256"]
+
+extern crate bytes;
 extern crate futures;
 extern crate serde;
 #[macro_use]
@@ -18,13 +22,53 @@ extern crate serde_derive;
 #[macro_use]
 extern crate quick_error;
 extern crate grpcio as grpc;
+extern crate rand;
+extern crate toml;
+#[cfg(feature = "metrics")]
+#[macro_use]
+extern crate lazy_static;
+#[cfg(feature = "metrics")]
+#[macro_use]
+extern crate prometheus;
+#[cfg(feature = "tracing")]
+#[macro_use]
+extern crate tracing;
+#[cfg(feature = "serialize")]
+extern crate base64;
+#[cfg(feature = "dns-srv")]
+extern crate trust_dns_resolver;
 
+pub mod codec;
+#[cfg(feature = "std-future")]
+pub mod compat;
+mod dns;
 pub mod errors;
+mod instrument;
+#[cfg(feature = "metrics")]
+mod metrics;
+#[cfg(feature = "mock")]
+mod mock;
 pub mod raw;
+mod region_cache;
+mod retry;
+mod security;
+#[cfg(feature = "serialize")]
+mod serialize;
+mod timer;
+#[cfg(feature = "tracing")]
+mod trace;
 pub mod transaction;
 
-use std::ops::Deref;
-use std::path::PathBuf;
+use std::cmp;
+use std::collections::HashMap;
+use std::fs;
+use std::ops::{
+    Bound, Deref, Range, RangeFrom, RangeFull, RangeInclusive, RangeTo, RangeToInclusive,
+};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use rand::Rng;
 
 pub use errors::Error;
 pub use errors::Result;
@@ -34,6 +78,7 @@ pub struct Key(Vec<u8>);
 #[derive(Default, Clone, Eq, PartialEq, Hash, Debug)]
 pub struct Value(Vec<u8>);
 #[derive(Default, Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub struct KvPair(Key, Value);
 
 impl Into<Key> for Vec<u8> {
@@ -42,12 +87,119 @@ impl Into<Key> for Vec<u8> {
     }
 }
 
+impl Into<Key> for String {
+    fn into(self) -> Key {
+        Key(self.into_bytes())
+    }
+}
+
+impl<'a> Into<Key> for &'a str {
+    fn into(self) -> Key {
+        Key(self.as_bytes().to_vec())
+    }
+}
+
+impl<'a> From<&'a [u8]> for Key {
+    fn from(key: &'a [u8]) -> Key {
+        Key(key.to_vec())
+    }
+}
+
+impl<'a> From<&'a Key> for Key {
+    fn from(key: &'a Key) -> Key {
+        key.clone()
+    }
+}
+
+/// A range of keys, as taken by scans and the other range operations of the raw
+/// client. Implemented for every standard range over types convertible into `Key`,
+/// so that `"a".."z"`, `key..=end` and `..` can all be passed directly.
+pub trait KeyRange {
+    fn into_bounds(self) -> (Bound<Key>, Bound<Key>);
+}
+
+fn convert_bound<T: Into<Key>>(bound: Bound<T>) -> Bound<Key> {
+    match bound {
+        Bound::Included(key) => Bound::Included(key.into()),
+        Bound::Excluded(key) => Bound::Excluded(key.into()),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+impl<T: Into<Key>> KeyRange for Range<T> {
+    fn into_bounds(self) -> (Bound<Key>, Bound<Key>) {
+        (
+            Bound::Included(self.start.into()),
+            Bound::Excluded(self.end.into()),
+        )
+    }
+}
+
+impl<T: Into<Key>> KeyRange for RangeInclusive<T> {
+    fn into_bounds(self) -> (Bound<Key>, Bound<Key>) {
+        let (start, end) = self.into_inner();
+        (Bound::Included(start.into()), Bound::Included(end.into()))
+    }
+}
+
+impl<T: Into<Key>> KeyRange for RangeFrom<T> {
+    fn into_bounds(self) -> (Bound<Key>, Bound<Key>) {
+        (Bound::Included(self.start.into()), Bound::Unbounded)
+    }
+}
+
+impl<T: Into<Key>> KeyRange for RangeTo<T> {
+    fn into_bounds(self) -> (Bound<Key>, Bound<Key>) {
+        (Bound::Unbounded, Bound::Excluded(self.end.into()))
+    }
+}
+
+impl<T: Into<Key>> KeyRange for RangeToInclusive<T> {
+    fn into_bounds(self) -> (Bound<Key>, Bound<Key>) {
+        (Bound::Unbounded, Bound::Included(self.end.into()))
+    }
+}
+
+impl KeyRange for RangeFull {
+    fn into_bounds(self) -> (Bound<Key>, Bound<Key>) {
+        (Bound::Unbounded, Bound::Unbounded)
+    }
+}
+
+impl<T: Into<Key>> KeyRange for (Bound<T>, Bound<T>) {
+    fn into_bounds(self) -> (Bound<Key>, Bound<Key>) {
+        (convert_bound(self.0), convert_bound(self.1))
+    }
+}
+
 impl AsRef<Key> for Key {
     fn as_ref(&self) -> &Self {
         self
     }
 }
 
+impl Key {
+    /// The smallest key greater than `self`, i.e. `self` followed by a zero byte.
+    pub fn next(&self) -> Key {
+        let mut next = self.0.clone();
+        next.push(0);
+        Key(next)
+    }
+
+    /// The smallest key greater than every key starting with `self`, or `None` if
+    /// there is none because `self` is empty or consists only of `0xFF` bytes.
+    pub fn prefix_end(&self) -> Option<Key> {
+        let mut end = self.0.clone();
+        while let Some(last) = end.pop() {
+            if last != 0xFF {
+                end.push(last + 1);
+                return Some(Key(end));
+            }
+        }
+        None
+    }
+}
+
 impl Deref for Key {
     type Target = Vec<u8>;
 
@@ -62,8 +214,38 @@ impl Into<Value> for Vec<u8> {
     }
 }
 
+impl Into<Value> for String {
+    fn into(self) -> Value {
+        Value(self.into_bytes())
+    }
+}
+
+impl<'a> Into<Value> for &'a str {
+    fn into(self) -> Value {
+        Value(self.as_bytes().to_vec())
+    }
+}
+
+impl Value {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.0
+    }
+}
+
 impl Deref for Value {
-    type Target = Vec<u8>;
+    type Target = [u8];
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -82,11 +264,223 @@ impl KvPair {
     pub fn value(&self) -> &Value {
         &self.1
     }
+
+    pub fn into_inner(self) -> (Key, Value) {
+        (self.0, self.1)
+    }
+}
+
+/// ```
+/// # use tikv_client::KvPair;
+/// let from_str: KvPair = ("k1", "v1").into();
+/// let from_vec: KvPair = (b"k2".to_vec(), b"v2".to_vec()).into();
+/// let from_string: KvPair = (String::from("k3"), String::from("v3")).into();
+/// ```
+impl<K, V> From<(K, V)> for KvPair
+where
+    K: Into<Key>,
+    V: Into<Value>,
+{
+    fn from((key, value): (K, V)) -> KvPair {
+        KvPair(key.into(), value.into())
+    }
+}
+
+impl From<KvPair> for (Key, Value) {
+    fn from(pair: KvPair) -> (Key, Value) {
+        pair.into_inner()
+    }
+}
+
+#[derive(Default, Clone, Eq, PartialEq, Debug)]
+pub struct Peer {
+    pub id: u64,
+    pub store_id: u64,
+    pub store_address: String,
+    /// The location labels of the peer's store, as reported by PD.
+    pub store_labels: HashMap<String, String>,
+}
+
+impl Peer {
+    /// Whether the peer's store carries every one of `labels` with the same value.
+    pub fn matches_labels(&self, labels: &HashMap<String, String>) -> bool {
+        labels
+            .iter()
+            .all(|(name, value)| self.store_labels.get(name) == Some(value))
+    }
 }
 
-impl Into<KvPair> for (Key, Value) {
-    fn into(self) -> KvPair {
-        KvPair(self.0, self.1)
+/// A region covering the keys in `[start_key, end_key)`. An empty `end_key` means
+/// the region extends to the end of the keyspace.
+#[derive(Default, Clone, Eq, PartialEq, Debug)]
+pub struct Region {
+    pub id: u64,
+    pub start_key: Key,
+    pub end_key: Key,
+    pub leader: Option<Peer>,
+    pub peers: Vec<Peer>,
+}
+
+impl Region {
+    pub fn contains(&self, key: impl AsRef<Key>) -> bool {
+        let key = key.as_ref();
+        self.start_key <= *key && (self.end_key.is_empty() || *key < self.end_key)
+    }
+
+    /// The peer to serve `ReadPolicy::ClosestReplica` reads for a client located at
+    /// `labels`: the leader if it matches, otherwise the first matching peer, and the
+    /// leader again if no peer matches.
+    pub fn closest_peer(&self, labels: &HashMap<String, String>) -> Option<&Peer> {
+        if labels.is_empty() {
+            return self.leader.as_ref();
+        }
+        self.leader
+            .iter()
+            .chain(self.peers.iter())
+            .find(|peer| peer.matches_labels(labels))
+            .or(self.leader.as_ref())
+    }
+}
+
+/// A leader move the client learned of after a request failed with `NotLeader` or
+/// `StaleEpoch`, passed to the callbacks registered with `Client::on_region_change`.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct RegionChange {
+    pub region_id: u64,
+    pub old_leader: Option<Peer>,
+    pub new_leader: Option<Peer>,
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backoff {
+    /// Retry immediately.
+    NoJitter,
+    /// Wait a random delay between zero and a ceiling that doubles after each
+    /// attempt, starting at `base` and capped at `max`. The randomness keeps clients
+    /// that failed together from retrying in lockstep.
+    FullJitter { base: Duration, max: Duration },
+    /// Double the delay after each attempt, starting at `base` and capped at `max`.
+    Exponential { base: Duration, max: Duration },
+}
+
+impl Backoff {
+    /// The delay before retry number `attempt`, counting from zero. Every request
+    /// counts its own attempts, so concurrent requests back off independently.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        match *self {
+            Backoff::NoJitter => Duration::from_secs(0),
+            Backoff::FullJitter { base, max } => {
+                let ceiling = Backoff::exponential(base, max, attempt);
+                let nanos = ceiling.as_secs() * 1_000_000_000 + u64::from(ceiling.subsec_nanos());
+                Duration::from_nanos(rand::thread_rng().gen_range(0, nanos + 1))
+            }
+            Backoff::Exponential { base, max } => Backoff::exponential(base, max, attempt),
+        }
+    }
+
+    fn exponential(base: Duration, max: Duration, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt).unwrap_or(u32::MAX);
+        base.checked_mul(factor)
+            .map_or(max, |delay| cmp::min(delay, max))
+    }
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub backoff: Backoff,
+}
+
+/// Compression applied to gRPC messages exchanged with TiKV.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+}
+
+/// The priority TiKV schedules a request with.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+// Under API V2 raw keys are prefixed with this mode byte and the 3-byte big-endian
+// keyspace id.
+const RAW_MODE: u8 = b'r';
+
+fn raw_key_prefix(keyspace_id: u32) -> [u8; 4] {
+    [
+        RAW_MODE,
+        (keyspace_id >> 16) as u8,
+        (keyspace_id >> 8) as u8,
+        keyspace_id as u8,
+    ]
+}
+
+/// The key format of the TiKV cluster, set by its `storage.api-version`.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ApiVersion {
+    /// Keys are stored as given.
+    #[default]
+    V1,
+    /// Raw keys are stored behind a mode byte and a keyspace id, `0` being the id of
+    /// the default keyspace.
+    V2,
+}
+
+impl ApiVersion {
+    /// The key sent to TiKV for the user key `key` of keyspace `keyspace_id`.
+    pub fn encode_key(self, keyspace_id: u32, key: &Key) -> Key {
+        match self {
+            ApiVersion::V1 => key.clone(),
+            ApiVersion::V2 => {
+                let mut encoded = raw_key_prefix(keyspace_id).to_vec();
+                encoded.extend_from_slice(key);
+                Key(encoded)
+            }
+        }
+    }
+
+    /// The user key for a key of keyspace `keyspace_id` returned by TiKV.
+    pub fn decode_key(self, keyspace_id: u32, key: Key) -> Result<Key> {
+        let prefix = raw_key_prefix(keyspace_id);
+        match self {
+            ApiVersion::V1 => Ok(key),
+            ApiVersion::V2 if key.starts_with(&prefix) => Ok(Key(key[prefix.len()..].to_vec())),
+            ApiVersion::V2 => Err(Error::Codec(format!(
+                "key {:?} is not a raw key of keyspace {}",
+                key, keyspace_id
+            ))),
+        }
+    }
+
+    /// Encodes a half-open `[start, end)` range, where an empty `end` means unbounded.
+    /// Under V2 an unbounded end is capped at the end of the keyspace, so that scans
+    /// never cross into another keyspace.
+    pub fn encode_range(self, keyspace_id: u32, range: (Key, Key)) -> (Key, Key) {
+        match self {
+            ApiVersion::V1 => range,
+            ApiVersion::V2 => {
+                let start = self.encode_key(keyspace_id, &range.0);
+                let end = if range.1.is_empty() {
+                    Key(raw_key_prefix(keyspace_id).to_vec())
+                        .prefix_end()
+                        .unwrap_or_default()
+                } else {
+                    self.encode_key(keyspace_id, &range.1)
+                };
+                (start, end)
+            }
+        }
     }
 }
 
@@ -98,18 +492,117 @@ pub struct Config {
     pub ca_path: Option<PathBuf>,
     pub cert_path: Option<PathBuf>,
     pub key_path: Option<PathBuf>,
+    #[serde(skip)]
+    pub ca_pem: Option<Vec<u8>>,
+    #[serde(skip)]
+    pub cert_pem: Option<Vec<u8>>,
+    #[serde(skip)]
+    pub key_pem: Option<Vec<u8>>,
+    pub timeout: Option<Duration>,
+    pub retry: Option<RetryPolicy>,
+    pub request_retry: Option<RetryPolicy>,
+    pub keepalive_interval: Option<Duration>,
+    pub keepalive_timeout: Option<Duration>,
+    pub max_message_size: Option<usize>,
+    pub max_key_size: Option<usize>,
+    pub max_value_size: Option<usize>,
+    pub compression: Compression,
+    pub labels: HashMap<String, String>,
+    pub default_priority: Priority,
+    pub api_version: ApiVersion,
+    pub keyspace: Option<String>,
+    pub max_scan_limit: Option<u32>,
+    pub clamp_scan_limit: bool,
+    pub coalesce_reads: bool,
+    pub region_cache_ttl: Option<Duration>,
+    pub connect_concurrency: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    pd_endpoints: Vec<String>,
+    timeout_ms: Option<u64>,
+    security: Option<SecurityFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SecurityFile {
+    ca_path: PathBuf,
+    cert_path: PathBuf,
+    key_path: PathBuf,
 }
 
 impl Config {
+    /// Each PD endpoint is a `host:port` address, or `pd+srv://name` to connect to the
+    /// targets of the DNS SRV records of `name`, e.g. the members of a Kubernetes
+    /// headless service, looked up when connecting. SRV lookups require the `dns-srv`
+    /// feature; if the lookup fails or the feature is disabled, `name` is used as a
+    /// plain host, on PD's default port 2379 unless it has a port.
     pub fn new(pd_endpoints: impl IntoIterator<Item = impl Into<String>>) -> Self {
         Config {
             pd_endpoints: pd_endpoints.into_iter().map(Into::into).collect(),
             ca_path: None,
             cert_path: None,
             key_path: None,
+            ca_pem: None,
+            cert_pem: None,
+            key_pem: None,
+            timeout: None,
+            retry: None,
+            request_retry: None,
+            keepalive_interval: None,
+            keepalive_timeout: None,
+            max_message_size: None,
+            max_key_size: None,
+            max_value_size: None,
+            compression: Compression::None,
+            labels: HashMap::new(),
+            default_priority: Priority::Normal,
+            api_version: ApiVersion::V1,
+            keyspace: None,
+            max_scan_limit: None,
+            clamp_scan_limit: false,
+            coalesce_reads: false,
+            region_cache_ttl: None,
+            connect_concurrency: None,
+        }
+    }
+
+    /// Parses a TOML document such as:
+    ///
+    /// ```toml
+    /// pd_endpoints = ["127.0.0.1:2379"]
+    /// timeout_ms = 3000
+    ///
+    /// [security]
+    /// ca_path = "/path/to/ca.pem"
+    /// cert_path = "/path/to/client.pem"
+    /// key_path = "/path/to/client-key.pem"
+    /// ```
+    ///
+    /// Unknown keys are rejected.
+    pub fn from_toml(s: &str) -> Result<Config> {
+        let file: ConfigFile = toml::from_str(s)?;
+        let mut config = Config::new(file.pd_endpoints);
+        if let Some(timeout_ms) = file.timeout_ms {
+            config = config.timeout(Duration::from_millis(timeout_ms));
+        }
+        if let Some(security) = file.security {
+            config = config.with_security(security.ca_path, security.cert_path, security.key_path);
         }
+        Ok(config)
+    }
+
+    /// Reads a TOML file in the format accepted by `from_toml`.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Config> {
+        Config::from_toml(&fs::read_to_string(path)?)
     }
 
+    /// Enables mutual TLS for all PD and TiKV connections, loading the PEM encoded
+    /// CA, certificate and private key from the given files. Unreadable files make
+    /// `Connect` fail with `Error::Security`.
     pub fn with_security(
         mut self,
         ca_path: impl Into<PathBuf>,
@@ -121,4 +614,277 @@ impl Config {
         self.key_path = Some(key_path.into());
         self
     }
+
+    /// Like `with_security`, but takes the PEM encoded contents directly.
+    pub fn with_security_pem(
+        mut self,
+        ca: impl Into<Vec<u8>>,
+        cert: impl Into<Vec<u8>>,
+        key: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.ca_pem = Some(ca.into());
+        self.cert_pem = Some(cert.into());
+        self.key_pem = Some(key.into());
+        self
+    }
+
+    /// Sets how long `Connect` waits for the initial PD handshake, including the
+    /// retries of `retry`, before failing with `Error::Timeout`. It also bounds each
+    /// `Get`, `Put`, `Delete` and `Scan` from its creation, see `Get::deadline`. There
+    /// is no timeout by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sends a keepalive ping on idle connections every `interval`, and closes a
+    /// connection whose ping isn't acknowledged within `timeout`. Keepalive is
+    /// disabled by default.
+    pub fn keepalive(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.keepalive_interval = Some(interval);
+        self.keepalive_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the largest gRPC message, in bytes, that can be sent or received. When
+    /// unset, gRPC's default receive limit of 4 MiB applies.
+    pub fn max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = Some(max_message_size);
+        self
+    }
+
+    /// Sets the longest key, in bytes, that `Get`, `Put` and `Delete` accept. Longer
+    /// keys fail with `Error::KeyTooLong` without contacting TiKV. Defaults to 4096,
+    /// matching TiKV's default `raftstore.max-key-size`.
+    pub fn max_key_size(mut self, max_key_size: usize) -> Self {
+        self.max_key_size = Some(max_key_size);
+        self
+    }
+
+    /// Sets the largest value, in bytes, that `Put` and `BatchPut` accept. Larger
+    /// values fail with `Error::ValueTooLarge`, which for a `BatchPut` carries the
+    /// index of the offending pair. Defaults to 8 MiB, TiKV's default
+    /// `raftstore.raft-entry-max-size`.
+    pub fn max_value_size(mut self, max_value_size: usize) -> Self {
+        self.max_value_size = Some(max_value_size);
+        self
+    }
+
+    /// Compresses gRPC messages and asks TiKV to compress its responses too. This
+    /// trades CPU on both ends for bandwidth, which mostly pays off for scans of
+    /// large values. TiKV answers uncompressed if it has compression disabled.
+    /// Defaults to `Compression::None`.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets the location labels of the client, e.g. `zone=us-east-1a`.
+    /// `ReadPolicy::ClosestReplica` reads from a peer whose store carries all of them.
+    pub fn labels(mut self, labels: HashMap<String, String>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    /// Shorthand for setting the `zone` label; see `labels`.
+    pub fn zone_label(mut self, zone: impl Into<String>) -> Self {
+        self.labels.insert("zone".to_owned(), zone.into());
+        self
+    }
+
+    /// Sets the priority of requests that don't set one with `priority(...)`.
+    /// Defaults to `Priority::Normal`.
+    pub fn default_priority(mut self, priority: Priority) -> Self {
+        self.default_priority = priority;
+        self
+    }
+
+    /// Sets the key format of the cluster, which must match its `storage.api-version`
+    /// or reads will miss. Under `ApiVersion::V2` the client prefixes keys and scan
+    /// bounds as described on `ApiVersion::encode_key` and `encode_range` before
+    /// sending them, and strips the prefix from returned keys. Defaults to
+    /// `ApiVersion::V1`.
+    pub fn api_version(mut self, api_version: ApiVersion) -> Self {
+        self.api_version = api_version;
+        self
+    }
+
+    /// Confines the client to the keyspace `name`, whose id `Connect` looks up in PD,
+    /// failing with `Error::KeyspaceNotFound` if there is none. Keys and scan bounds
+    /// are prefixed with the keyspace id, so clients of different keyspaces never see
+    /// each other's data. Keyspaces require, and this selects, `ApiVersion::V2`.
+    pub fn keyspace(mut self, name: impl Into<String>) -> Self {
+        self.keyspace = Some(name.into());
+        self.api_version = ApiVersion::V2;
+        self
+    }
+
+    /// Caps the `limit` of `Scan`, `ScanKeys` and `BatchScan` requests, and the page
+    /// size of a `Paginator`, so that a bogus limit such as `u32::MAX` can't pull a
+    /// whole keyspace into memory. Larger limits fail with `Error::ScanLimitExceeded`
+    /// unless `clamp_scan_limit` is set. Streams from `Scan::into_stream` are exempt,
+    /// since they only hold one batch at a time.
+    pub fn max_scan_limit(mut self, max_scan_limit: u32) -> Self {
+        self.max_scan_limit = Some(max_scan_limit);
+        self
+    }
+
+    /// Makes scans over `max_scan_limit` return at most that many pairs instead of
+    /// failing.
+    pub fn clamp_scan_limit(mut self, clamp: bool) -> Self {
+        self.clamp_scan_limit = clamp;
+        self
+    }
+
+    /// Makes concurrent `Get`s of the same key and column family share a single
+    /// request to TiKV, all resolving to its value, or all failing with the same
+    /// `Error::Shared`. This spares the leader of a hot key, but a `Get` that joins a
+    /// request already in flight may miss a write acknowledged after that request was
+    /// sent. Only `Get`s with the default options are coalesced: setting `stale`,
+    /// `priority`, `read_policy` or `deadline` on a `Get` makes it send its own
+    /// request. Disabled by default.
+    pub fn coalesce_reads(mut self, coalesce: bool) -> Self {
+        self.coalesce_reads = coalesce;
+        self
+    }
+
+    /// Makes the client resolve a region from PD again once its routing has been
+    /// cached for `ttl`, even if no request failed with it. This bounds how long a
+    /// leader that moved silently keeps being tried first. A zero `ttl`, like the
+    /// default, trusts cached regions until a region error invalidates them.
+    pub fn region_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.region_cache_ttl = Some(ttl);
+        self
+    }
+
+    /// Makes `Connect` open a channel to every store of the cluster before resolving,
+    /// up to `concurrency` at a time, so that the first request to each store doesn't
+    /// pay for the connection. By default channels are opened lazily, on first use.
+    pub fn connect_concurrency(mut self, concurrency: usize) -> Self {
+        self.connect_concurrency = Some(cmp::max(concurrency, 1));
+        self
+    }
+
+    /// Makes `Connect` cycle through the PD endpoints in order, waiting according to
+    /// `backoff` between attempts, and fail with `Error::ConnectFailed` after
+    /// `max_attempts` unsuccessful attempts. An endpoint failing with an error that
+    /// isn't `Error::is_retryable`, e.g. `Error::Security`, fails `Connect` right
+    /// away. By default each endpoint is tried once, without waiting.
+    ///
+    /// The same policy bounds the retries of requests failing with a region error, as
+    /// described on `raw::Client`. By default those make up to 10 attempts, with
+    /// `Backoff::FullJitter` between 2ms and 500ms.
+    pub fn retry(mut self, max_attempts: u32, backoff: Backoff) -> Self {
+        self.retry = Some(RetryPolicy {
+            max_attempts,
+            backoff,
+        });
+        self
+    }
+
+    /// Makes request futures such as `Get` and `Put` retry up to `max_attempts` times,
+    /// waiting according to `backoff`, when a store reports `ServerIsBusy` or gRPC
+    /// returns `Unavailable`. Each retry is sent to the region's current leader.
+    /// Writes that aren't safe to repeat should opt out with `idempotent(false)`.
+    /// Region errors are retried according to `retry` instead, and their attempts
+    /// are counted separately. By default these errors are not retried.
+    pub fn request_retry(mut self, max_attempts: u32, backoff: Backoff) -> Self {
+        self.request_retry = Some(RetryPolicy {
+            max_attempts,
+            backoff,
+        });
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp;
+    use std::time::Duration;
+
+    use {ApiVersion, Backoff, Key};
+
+    fn key(bytes: &[u8]) -> Key {
+        bytes.to_vec().into()
+    }
+
+    #[test]
+    fn key_next() {
+        assert_eq!(Key::default().next(), key(&[0]));
+        assert_eq!(key(b"a").next(), key(b"a\0"));
+        assert_eq!(key(&[0xFF]).next(), key(&[0xFF, 0]));
+        assert!(key(b"a") < key(b"a").next());
+    }
+
+    #[test]
+    fn key_prefix_end() {
+        assert_eq!(Key::default().prefix_end(), None);
+        assert_eq!(key(&[0xFF, 0xFF]).prefix_end(), None);
+        assert_eq!(key(b"ab").prefix_end(), Some(key(b"ac")));
+        assert_eq!(key(&[b'a', 0xFF]).prefix_end(), Some(key(b"b")));
+        assert_eq!(key(&[b'a', 0xFF, 0xFF]).prefix_end(), Some(key(b"b")));
+        let end = key(&[b'a', 0xFF]).prefix_end().unwrap();
+        assert!(key(&[b'a', 0xFF, 0xFF, 0xFF]) < end);
+    }
+
+    fn millis(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn exponential_backoff() {
+        let backoff = Backoff::Exponential {
+            base: millis(10),
+            max: millis(100),
+        };
+        assert_eq!(backoff.delay(0), millis(10));
+        assert_eq!(backoff.delay(1), millis(20));
+        assert_eq!(backoff.delay(3), millis(80));
+        assert_eq!(backoff.delay(4), millis(100));
+        assert_eq!(backoff.delay(40), millis(100));
+        assert_eq!(Backoff::NoJitter.delay(3), millis(0));
+    }
+
+    #[test]
+    fn full_jitter_stays_within_cap() {
+        let backoff = Backoff::FullJitter {
+            base: millis(10),
+            max: millis(100),
+        };
+        for attempt in 0..40 {
+            let cap = cmp::min(millis(10) * (1 << cmp::min(attempt, 4)), millis(100));
+            let delays: Vec<Duration> = (0..100).map(|_| backoff.delay(attempt)).collect();
+            assert!(delays.iter().all(|delay| *delay <= cap), "attempt {}", attempt);
+            assert!(delays.iter().any(|delay| *delay < cap), "attempt {}", attempt);
+        }
+    }
+
+    #[test]
+    fn api_version_encodes_keys() {
+        let encoded = ApiVersion::V2.encode_key(0x01_0203, &key(b"k"));
+        assert_eq!(encoded, key(b"r\x01\x02\x03k"));
+        assert_eq!(ApiVersion::V2.decode_key(0x01_0203, encoded).unwrap(), key(b"k"));
+        assert!(ApiVersion::V2.decode_key(7, key(b"r\x01\x02\x03k")).is_err());
+        assert!(ApiVersion::V2.decode_key(7, key(b"k")).is_err());
+        assert_eq!(ApiVersion::V1.encode_key(7, &key(b"k")), key(b"k"));
+        assert_eq!(ApiVersion::V1.decode_key(7, key(b"k")).unwrap(), key(b"k"));
+    }
+
+    #[test]
+    fn api_version_encodes_ranges() {
+        let range = |start: &[u8], end: &[u8]| (key(start), key(end));
+        assert_eq!(
+            ApiVersion::V2.encode_range(1, range(b"a", b"b")),
+            range(b"r\0\0\x01a", b"r\0\0\x01b")
+        );
+        assert_eq!(
+            ApiVersion::V2.encode_range(1, range(b"", b"")),
+            range(b"r\0\0\x01", b"r\0\0\x02")
+        );
+        assert_eq!(
+            ApiVersion::V2.encode_range(0xFF_FFFF, range(b"a", b"")),
+            range(b"r\xff\xff\xffa", b"s")
+        );
+        assert_eq!(ApiVersion::V1.encode_range(1, range(b"a", b"")), range(b"a", b""));
+    }
 }
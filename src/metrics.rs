@@ -0,0 +1,105 @@
+// Copyright 2018 The TiKV Project Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Recorded by the request futures through `Instrument` once they complete.
+
+use std::cell::Cell;
+use std::time::Instant;
+
+use prometheus::{HistogramVec, IntCounterVec};
+
+use {Error, Result};
+
+lazy_static! {
+    static ref REQUEST_DURATION_HISTOGRAM_VEC: HistogramVec = register_histogram_vec!(
+        "tikv_client_request_duration_seconds",
+        "Bucketed histogram of TiKV client request duration",
+        &["type", "cf"]
+    )
+    .unwrap();
+    // Labeled by store id rather than address, which keeps the cardinality bounded
+    // by the size of the cluster even as stores move between hosts.
+    static ref STORE_REQUEST_DURATION_HISTOGRAM_VEC: HistogramVec = register_histogram_vec!(
+        "tikv_client_store_request_duration_seconds",
+        "Bucketed histogram of TiKV client request duration per target store",
+        &["type", "store"]
+    )
+    .unwrap();
+    static ref REQUEST_ERROR_COUNTER_VEC: IntCounterVec = register_int_counter_vec!(
+        "tikv_client_request_error_total",
+        "Total number of failed TiKV client requests",
+        &["type", "category"]
+    )
+    .unwrap();
+}
+
+fn error_category(err: &Error) -> &'static str {
+    match *err {
+        Error::Shared(ref err) => error_category(err),
+        Error::Io(_) => "io",
+        Error::Grpc(_) => "grpc",
+        Error::Timeout => "timeout",
+        Error::RegionError(_)
+        | Error::RegionForKeyNotFound(_)
+        | Error::RegionNotFound(_)
+        | Error::NotLeader(_)
+        | Error::StaleEpoch
+        | Error::StoreNotMatch => "region",
+        Error::KeyError { .. } => "key",
+        Error::ServerIsBusy(_) => "server_is_busy",
+        _ => "other",
+    }
+}
+
+pub struct RequestStats {
+    start: Instant,
+    cmd: &'static str,
+    cf: String,
+    store_id: Cell<Option<u64>>,
+}
+
+impl RequestStats {
+    pub fn new(cmd: &'static str, cf: &str) -> Self {
+        RequestStats {
+            start: Instant::now(),
+            cmd,
+            cf: cf.to_owned(),
+            store_id: Cell::new(None),
+        }
+    }
+
+    /// Records the store the request was sent to once its region is resolved, so that
+    /// its latency is also reported per store. A retry against another store replaces
+    /// it.
+    pub fn store(&self, store_id: u64) {
+        self.store_id.set(Some(store_id));
+    }
+
+    pub fn done<T>(&self, result: &Result<T>) {
+        let elapsed = self.start.elapsed();
+        let secs = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+        REQUEST_DURATION_HISTOGRAM_VEC
+            .with_label_values(&[self.cmd, &self.cf])
+            .observe(secs);
+        if let Some(store_id) = self.store_id.get() {
+            STORE_REQUEST_DURATION_HISTOGRAM_VEC
+                .with_label_values(&[self.cmd, &store_id.to_string()])
+                .observe(secs);
+        }
+        if let Err(ref err) = *result {
+            REQUEST_ERROR_COUNTER_VEC
+                .with_label_values(&[self.cmd, error_category(err)])
+                .inc();
+        }
+    }
+}
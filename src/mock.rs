@@ -0,0 +1,410 @@
+// Copyright 2018 The TiKV Project Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::ops::Bound;
+use std::sync::{Mutex, RwLock};
+
+use futures::task::{self, Task};
+use futures::{Async, Poll};
+
+use raw::{ChecksumResult, ColumnFamily};
+use {Error, Key, KvPair, Peer, Region, Value};
+
+/// The in-memory store behind `Client::new_mock`, one ordered map per column family.
+///
+/// The store also stands in for PD: it keeps a region layout, a single region until
+/// a test splits it, that the requests are routed with and checked against.
+pub struct MockStore {
+    cfs: RwLock<HashMap<ColumnFamily, BTreeMap<Key, Value>>>,
+    // In key order, covering the whole keyspace.
+    regions: RwLock<Vec<Region>>,
+    // The errors the next requests fail with, in order; see `fail_next`.
+    failures: Mutex<VecDeque<Error>>,
+    // The attempts received, by request and target; see `requests`.
+    requests: Mutex<Vec<(&'static str, u64)>>,
+    // The tasks of the attempts held back while paused; see `pause`.
+    paused: Mutex<Option<Vec<Task>>>,
+    // The ids of the keyspaces, standing in for PD's `LoadKeyspace`.
+    keyspaces: Mutex<HashMap<String, u32>>,
+}
+
+impl Default for MockStore {
+    fn default() -> Self {
+        MockStore {
+            cfs: RwLock::default(),
+            regions: RwLock::new(vec![mock_region(1, Key::default(), Key::default(), 1)]),
+            failures: Mutex::default(),
+            requests: Mutex::default(),
+            paused: Mutex::default(),
+            keyspaces: Mutex::default(),
+        }
+    }
+}
+
+fn mock_region(id: u64, start_key: Key, end_key: Key, store_id: u64) -> Region {
+    let leader = Peer {
+        id,
+        store_id,
+        store_address: format!("mock-store-{}", store_id),
+        ..Peer::default()
+    };
+    Region {
+        id,
+        start_key,
+        end_key,
+        leader: Some(leader.clone()),
+        peers: vec![leader],
+    }
+}
+
+// CRC-64/XZ, the crc64 of TiKV's checksum request.
+fn crc64(data: &[u8]) -> u64 {
+    let mut crc = !0u64;
+    for byte in data {
+        crc ^= u64::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xC96C_5795_D787_0F42
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn cf_or_default(cf: &Option<ColumnFamily>) -> ColumnFamily {
+    cf.clone().unwrap_or(ColumnFamily::DEFAULT)
+}
+
+fn bounds(range: &(Key, Key)) -> (Bound<&Key>, Bound<&Key>) {
+    let end = if range.1.is_empty() {
+        Bound::Unbounded
+    } else {
+        Bound::Excluded(&range.1)
+    };
+    (Bound::Included(&range.0), end)
+}
+
+impl MockStore {
+    /// Makes the next attempt of a request fail with `err` without touching the
+    /// store. Errors queued by several calls are taken in order.
+    #[cfg(test)]
+    pub fn fail_next(&self, err: Error) {
+        self.failures.lock().unwrap().push_back(err);
+    }
+
+    /// Replaces the region layout with regions split at `split_keys`, in ascending
+    /// order, whose leaders are on `stores`, one per region. The regions get new ids,
+    /// so that requests routed with the previous layout fail with
+    /// `Error::RegionNotFound`, as they would after a split.
+    #[cfg(test)]
+    pub fn split(&self, split_keys: &[Key], stores: &[u64]) {
+        assert_eq!(split_keys.len() + 1, stores.len());
+        let mut regions = self.regions.write().unwrap();
+        let first_id = regions.iter().map(|region| region.id).max().unwrap_or(0) + 1;
+        let starts = ::std::iter::once(Key::default()).chain(split_keys.iter().cloned());
+        let ends = split_keys
+            .iter()
+            .cloned()
+            .chain(::std::iter::once(Key::default()));
+        *regions = starts
+            .zip(ends)
+            .zip(stores)
+            .enumerate()
+            .map(|(i, ((start, end), store_id))| {
+                mock_region(first_id + i as u64, start, end, *store_id)
+            })
+            .collect();
+    }
+
+    /// Holds back every attempt until `resume`: they don't reach the store and their
+    /// requests stay pending.
+    #[cfg(test)]
+    pub fn pause(&self) {
+        *self.paused.lock().unwrap() = Some(Vec::new());
+    }
+
+    /// Wakes the requests held back since `pause`.
+    #[cfg(test)]
+    pub fn resume(&self) {
+        for task in self.paused.lock().unwrap().take().unwrap_or_default() {
+            task.notify();
+        }
+    }
+
+    /// The number of attempts held back since `pause`.
+    #[cfg(test)]
+    pub fn parked(&self) -> usize {
+        self.paused.lock().unwrap().as_ref().map_or(0, Vec::len)
+    }
+
+    /// The targets of the attempts of `request` received so far, in order: region ids
+    /// for the requests sent to a region, store ids for those sent to a store.
+    #[cfg(test)]
+    pub fn requests(&self, request: &str) -> Vec<u64> {
+        let requests = self.requests.lock().unwrap();
+        requests
+            .iter()
+            .filter(|(name, _)| *name == request)
+            .map(|(_, target)| *target)
+            .collect()
+    }
+
+    /// Creates the keyspace `name`, if it doesn't exist yet, returning its id.
+    #[cfg(test)]
+    pub fn create_keyspace(&self, name: &str) -> u32 {
+        let mut keyspaces = self.keyspaces.lock().unwrap();
+        let next_id = keyspaces.len() as u32 + 1;
+        *keyspaces.entry(name.to_owned()).or_insert(next_id)
+    }
+
+    /// The id of the keyspace `name`, as PD would report it.
+    pub fn keyspace_id(&self, name: &str) -> Option<u32> {
+        self.keyspaces.lock().unwrap().get(name).cloned()
+    }
+
+    /// The region containing `key`, as PD would report it.
+    pub fn region(&self, key: &Key) -> Region {
+        let regions = self.regions.read().unwrap();
+        regions
+            .iter()
+            .find(|region| region.contains(key))
+            .cloned()
+            .unwrap()
+    }
+
+    /// The region containing the keys right below `key`, an empty `key` standing for
+    /// the end of the keyspace.
+    pub fn region_before(&self, key: &Key) -> Region {
+        let regions = self.regions.read().unwrap();
+        regions
+            .iter()
+            .find(|region| {
+                if key.is_empty() {
+                    region.end_key.is_empty()
+                } else {
+                    region.start_key < *key && (region.end_key.is_empty() || region.end_key >= *key)
+                }
+            })
+            .cloned()
+            .unwrap()
+    }
+
+    /// The regions overlapping `range`, in key order.
+    pub fn regions_in(&self, range: &(Key, Key)) -> Vec<Region> {
+        let regions = self.regions.read().unwrap();
+        regions
+            .iter()
+            .filter(|region| {
+                (region.end_key.is_empty() || region.end_key > range.0)
+                    && (range.1.is_empty() || region.start_key < range.1)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Called by the request futures before each attempt of `request` sent to the
+    /// store `target`, or to a region for a request that isn't routed by key. Fails
+    /// with the error queued by `fail_next`, if any.
+    pub fn poll_request(&self, request: &'static str, target: u64) -> Poll<(), Error> {
+        if let Some(ref mut parked) = *self.paused.lock().unwrap() {
+            parked.push(task::current());
+            return Ok(Async::NotReady);
+        }
+        self.requests.lock().unwrap().push((request, target));
+        match self.failures.lock().unwrap().pop_front() {
+            Some(err) => Err(err),
+            None => Ok(Async::Ready(())),
+        }
+    }
+
+    /// Like `poll_request`, for an attempt routed to `region`, which fails with
+    /// `Error::RegionNotFound` if the region has been split since it was resolved.
+    pub fn poll_region_request(&self, request: &'static str, region: &Region) -> Poll<(), Error> {
+        if let Async::NotReady = self.poll_request(request, region.id)? {
+            return Ok(Async::NotReady);
+        }
+        let regions = self.regions.read().unwrap();
+        let current = regions.iter().any(|current| {
+            current.id == region.id
+                && current.start_key == region.start_key
+                && current.end_key == region.end_key
+        });
+        if !current {
+            return Err(Error::RegionNotFound(region.id));
+        }
+        Ok(Async::Ready(()))
+    }
+
+    /// Like `poll_region_request`, for an attempt carrying `keys`, which fails with
+    /// `Error::KeyNotInRegion` if one of them is outside of `region`.
+    pub fn poll_keys_request<'k, I>(
+        &self,
+        request: &'static str,
+        region: &Region,
+        keys: I,
+    ) -> Poll<(), Error>
+    where
+        I: IntoIterator<Item = &'k Key>,
+    {
+        if let Async::NotReady = self.poll_region_request(request, region)? {
+            return Ok(Async::NotReady);
+        }
+        if let Some(key) = keys.into_iter().find(|key| !region.contains(key)) {
+            return Err(Error::KeyNotInRegion(
+                key.to_vec(),
+                region.id,
+                region.start_key.to_vec(),
+                region.end_key.to_vec(),
+            ));
+        }
+        Ok(Async::Ready(()))
+    }
+
+    pub fn get(&self, cf: &Option<ColumnFamily>, key: &Key) -> Option<Value> {
+        let cfs = self.cfs.read().unwrap();
+        cfs.get(&cf_or_default(cf))
+            .and_then(|data| data.get(key))
+            .cloned()
+    }
+
+    pub fn batch_get(&self, cf: &Option<ColumnFamily>, keys: &[Key]) -> Vec<KvPair> {
+        keys.iter()
+            .filter_map(|key| {
+                self.get(cf, key)
+                    .map(|value| KvPair::new(key.clone(), value))
+            })
+            .collect()
+    }
+
+    /// Returns the value previously stored at `key`.
+    pub fn put(&self, cf: &Option<ColumnFamily>, key: Key, value: Value) -> Option<Value> {
+        let mut cfs = self.cfs.write().unwrap();
+        cfs.entry(cf_or_default(cf)).or_default().insert(key, value)
+    }
+
+    pub fn batch_put(&self, cf: &Option<ColumnFamily>, pairs: &[KvPair]) {
+        for pair in pairs {
+            self.put(cf, pair.key().clone(), pair.value().clone());
+        }
+    }
+
+    /// Returns whether `key` existed.
+    pub fn delete(&self, cf: &Option<ColumnFamily>, key: &Key) -> bool {
+        let mut cfs = self.cfs.write().unwrap();
+        cfs.get_mut(&cf_or_default(cf))
+            .and_then(|data| data.remove(key))
+            .is_some()
+    }
+
+    pub fn batch_delete(&self, cf: &Option<ColumnFamily>, keys: &[Key]) {
+        for key in keys {
+            self.delete(cf, key);
+        }
+    }
+
+    pub fn scan(
+        &self,
+        cf: &Option<ColumnFamily>,
+        range: &(Key, Key),
+        limit: u32,
+        reverse: bool,
+        prefix: Option<&Key>,
+    ) -> Vec<KvPair> {
+        let cfs = self.cfs.read().unwrap();
+        let data = match cfs.get(&cf_or_default(cf)) {
+            Some(data) => data,
+            None => return Vec::new(),
+        };
+        let pairs = data
+            .range::<Key, _>(bounds(range))
+            .filter(|(key, _)| prefix.is_none_or(|prefix| key.starts_with(prefix)))
+            .map(|(key, value)| KvPair::new(key.clone(), value.clone()));
+        if reverse {
+            pairs.rev().take(limit as usize).collect()
+        } else {
+            pairs.take(limit as usize).collect()
+        }
+    }
+
+    /// Returns the number of keys deleted, or that would be deleted if `dry_run`.
+    pub fn delete_range(
+        &self,
+        cf: &Option<ColumnFamily>,
+        range: &(Key, Key),
+        limit: u32,
+        dry_run: bool,
+    ) -> u32 {
+        let keys: Vec<Key> = self
+            .scan(cf, range, limit, false, None)
+            .into_iter()
+            .map(|pair| pair.into_inner().0)
+            .collect();
+        if !dry_run {
+            self.batch_delete(cf, &keys);
+        }
+        keys.len() as u32
+    }
+
+    /// Removes the keys in `range` from every column family.
+    pub fn destroy_range(&self, range: &(Key, Key)) {
+        let mut cfs = self.cfs.write().unwrap();
+        for data in cfs.values_mut() {
+            let keys: Vec<Key> = data
+                .range::<Key, _>(bounds(range))
+                .map(|(key, _)| key.clone())
+                .collect();
+            for key in keys {
+                data.remove(&key);
+            }
+        }
+    }
+
+    /// The checksum of the pairs of the default column family in `range`, computed as
+    /// TiKV does: the crc64 of each key followed by its value, XORed.
+    pub fn checksum(&self, range: &(Key, Key)) -> ChecksumResult {
+        let cfs = self.cfs.read().unwrap();
+        let mut result = ChecksumResult::default();
+        if let Some(data) = cfs.get(&ColumnFamily::DEFAULT) {
+            for (key, value) in data.range::<Key, _>(bounds(range)) {
+                let mut pair = key.to_vec();
+                pair.extend_from_slice(value);
+                result.checksum ^= crc64(&pair);
+                result.total_kvs += 1;
+                result.total_bytes += pair.len() as u64;
+            }
+        }
+        result
+    }
+
+    /// Returns the previous value, or an empty one, and whether the swap happened.
+    pub fn compare_and_swap(
+        &self,
+        cf: &Option<ColumnFamily>,
+        key: &Key,
+        previous_value: &Option<Value>,
+        new_value: &Value,
+    ) -> (Value, bool) {
+        let mut cfs = self.cfs.write().unwrap();
+        let data = cfs.entry(cf_or_default(cf)).or_default();
+        let current = data.get(key).cloned();
+        if current == *previous_value {
+            data.insert(key.clone(), new_value.clone());
+            (current.unwrap_or_default(), true)
+        } else {
+            (current.unwrap_or_default(), false)
+        }
+    }
+}
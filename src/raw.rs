@@ -11,36 +11,177 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::ops::RangeBounds;
+use std::borrow::Cow;
+use std::cmp;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
+use std::fmt;
+use std::iter;
+use std::mem;
+use std::ops::{Bound, Deref, RangeBounds};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use std::vec;
 
-use futures::{Future, Poll};
+use bytes::Bytes;
+use futures::sync::oneshot;
+use futures::task::{self, Task};
+use futures::{Async, AsyncSink, Future, Poll, Sink, StartSend, Stream};
+use grpc::{Channel, Environment};
 
-use {Config, Error, Key, KvPair, Value};
+use codec;
+use dns;
+use instrument::Instrument;
+#[cfg(feature = "mock")]
+use mock::MockStore;
+use region_cache::RegionCache;
+use retry::{Failover, Retry};
+use security::{self, SecurityManager};
+use timer::{Deadline, Timeout};
+use transaction::Timestamp;
+use {Config, Error, Key, KeyRange, KvPair, Peer, Priority, Region, RegionChange, Value};
 
+/// A RocksDB column family on TiKV.
+///
+/// The raw API can address the `default`, `write` and `lock` column families. Prefer
+/// the associated constants or `TryFrom<&str>`, which rejects unknown names, as
+/// `str::parse` and deserializing do; the permissive `From<String>` conversion is kept
+/// for clusters configured with custom CFs.
 #[derive(Default, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
-pub struct ColumnFamily(String);
+pub struct ColumnFamily(Cow<'static, str>);
 
-impl<T> From<T> for ColumnFamily
-where
-    T: ToString,
-{
-    fn from(i: T) -> ColumnFamily {
-        ColumnFamily(i.to_string())
+impl ColumnFamily {
+    pub const DEFAULT: ColumnFamily = ColumnFamily(Cow::Borrowed("default"));
+    pub const WRITE: ColumnFamily = ColumnFamily(Cow::Borrowed("write"));
+    pub const LOCK: ColumnFamily = ColumnFamily(Cow::Borrowed("lock"));
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for ColumnFamily {
+    fn from(name: String) -> ColumnFamily {
+        ColumnFamily(Cow::Owned(name))
+    }
+}
+
+impl<'a> From<&'a String> for ColumnFamily {
+    fn from(name: &'a String) -> ColumnFamily {
+        name.clone().into()
+    }
+}
+
+impl<'a> TryFrom<&'a str> for ColumnFamily {
+    type Error = Error;
+
+    fn try_from(name: &'a str) -> Result<ColumnFamily, Error> {
+        match name {
+            "default" => Ok(ColumnFamily::DEFAULT),
+            "write" => Ok(ColumnFamily::WRITE),
+            "lock" => Ok(ColumnFamily::LOCK),
+            _ => Err(Error::UnknownColumnFamily(name.to_owned())),
+        }
+    }
+}
+
+impl AsRef<str> for ColumnFamily {
+    fn as_ref(&self) -> &str {
+        self.as_str()
     }
 }
 
+impl fmt::Display for ColumnFamily {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ColumnFamily {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ColumnFamily::try_from(s)
+    }
+}
+
+#[cfg(feature = "serialize")]
+impl ::serde::Serialize for ColumnFamily {
+    fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serialize")]
+impl<'de> ::serde::Deserialize<'de> for ColumnFamily {
+    fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(::serde::de::Error::custom)
+    }
+}
+
+/// Which replica of a region serves a read.
+///
+/// Follower reads still observe every write committed before the read started, as
+/// the follower first waits to catch up with the leader's commit index, but they cost
+/// an extra round trip between the follower and the leader.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum ReadPolicy {
+    /// Read from the region leader.
+    #[default]
+    Leader,
+    /// Read from a peer other than the leader, setting `replica_read` on the request.
+    Follower,
+    /// Read from a peer whose store matches `Config::labels`, falling back to the
+    /// leader. See `Region::closest_peer`.
+    ClosestReplica,
+}
+
+const DEFAULT_MAX_KEY_SIZE: usize = 4096;
+const DEFAULT_MAX_VALUE_SIZE: usize = 8 * 1024 * 1024;
+
 pub struct Get<'a> {
     client: &'a Client,
     key: Key,
     cf: Option<ColumnFamily>,
+    priority: Priority,
+    deadline: Option<Instant>,
+    // Bounds the request by `deadline` and `Config::timeout`.
+    timeout: Deadline,
+    read_policy: ReadPolicy,
+    max_staleness: Option<Duration>,
+    error: Option<Error>,
+    retry: Retry,
+    // With `Config::coalesce_reads`, whether this `Get` sends the request that the
+    // `Get`s of the same key in `Inner::reads_in_flight` wait for...
+    leader: bool,
+    // ... or the receiving end of the one it waits for itself.
+    waiter: Option<oneshot::Receiver<SharedRead>>,
+    instrument: Instrument,
+    in_flight: Option<InFlight<'a>>,
 }
 
 impl<'a> Get<'a> {
     fn new(client: &'a Client, key: Key) -> Self {
         Get {
+            error: client.check_key(&key).err(),
             client,
             key,
-            cf: None,
+            cf: client.cf.clone(),
+            priority: client.inner.config.default_priority,
+            deadline: None,
+            timeout: client.request_timeout(None),
+            read_policy: ReadPolicy::default(),
+            max_staleness: None,
+            retry: Retry::new(&client.inner.config),
+            leader: false,
+            waiter: None,
+            instrument: Instrument::default(),
+            in_flight: None,
         }
     }
 
@@ -48,134 +189,261 @@ impl<'a> Get<'a> {
         self.cf = Some(cf.into());
         self
     }
-}
-
-impl<'a> Future for Get<'a> {
-    type Item = Value;
-    type Error = Error;
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        let _ = &self.client;
-        let _ = &self.key;
-        let _ = &self.cf;
-        unimplemented!()
+    /// Performs a stale read: the value as of `max_staleness` ago, served by the
+    /// closest replica without contacting the leader.
+    ///
+    /// This is NOT a consistent read. Writes committed within the last
+    /// `max_staleness` may be missing, and two stale reads may observe different
+    /// points in time. The replica answers only if its safe time has passed the read
+    /// timestamp, i.e. it has applied every write up to that point; otherwise the read
+    /// is sent again to the leader, which always can. Overrides `read_policy` with
+    /// `ReadPolicy::ClosestReplica`.
+    pub fn stale(mut self, max_staleness: Duration) -> Self {
+        self.max_staleness = Some(max_staleness);
+        self.read_policy = ReadPolicy::ClosestReplica;
+        self
     }
-}
 
-pub struct BatchGet<'a> {
-    client: &'a Client,
-    keys: Vec<Key>,
-    cf: Option<ColumnFamily>,
-}
+    /// Sets the priority TiKV schedules the request with, sent as `Context.priority`.
+    /// Defaults to `Config::default_priority`. TiKV only honors it for requests that
+    /// queue in its scheduler or read pool, e.g. to keep a large `Low` priority scan
+    /// from delaying interactive reads.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
 
-impl<'a> BatchGet<'a> {
-    fn new(client: &'a Client, keys: Vec<Key>) -> Self {
-        BatchGet {
-            client,
-            keys,
-            cf: None,
-        }
+    /// Picks the replica that serves the read. Defaults to `ReadPolicy::Leader`.
+    pub fn read_policy(mut self, read_policy: ReadPolicy) -> Self {
+        self.read_policy = read_policy;
+        self
     }
 
-    pub fn cf(mut self, cf: impl Into<ColumnFamily>) -> Self {
-        self.cf = Some(cf.into());
+    /// Fails the request with `Error::Timeout` if it hasn't completed by `deadline`.
+    /// The deadline is also sent to TiKV as the gRPC deadline; if `Config::timeout`
+    /// is set as well, whichever expires first applies.
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self.timeout = self.client.request_timeout(self.deadline);
         self
     }
 }
 
-impl<'a> Future for BatchGet<'a> {
-    type Item = Vec<KvPair>;
-    type Error = ();
+impl<'a> Future for Get<'a> {
+    type Item = Option<Value>;
+    type Error = Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        let _ = &self.client;
-        let _ = &self.keys;
-        let _ = &self.cf;
-        unimplemented!()
+        let _entered = self.instrument.enter("get", self.key.len(), &self.cf);
+        let result = self.timeout.check().and_then(|()| self.poll_get());
+        let result = self.timeout.poll(result);
+        self.instrument.done(result)
     }
 }
 
-pub struct Put<'a> {
-    client: &'a Client,
-    key: Key,
-    value: Value,
-    cf: Option<ColumnFamily>,
+impl<'a> Get<'a> {
+    fn poll_get(&mut self) -> Poll<Option<Value>, Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        if self.coalesces() {
+            if !self.leader && self.waiter.is_none() {
+                let mut reads = self.client.inner.reads_in_flight.lock().unwrap();
+                match reads.entry((self.cf.clone(), self.key.clone())) {
+                    Entry::Occupied(mut entry) => {
+                        let (sender, receiver) = oneshot::channel();
+                        entry.get_mut().push(sender);
+                        self.waiter = Some(receiver);
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert(Vec::new());
+                        self.leader = true;
+                    }
+                }
+            }
+            let shared = match self.waiter.as_mut().map(Future::poll) {
+                Some(Ok(Async::Ready(shared))) => shared,
+                Some(Ok(Async::NotReady)) => return Ok(Async::NotReady),
+                // The leader was dropped before completing, so read on our own.
+                Some(Err(_)) => {
+                    self.waiter = None;
+                    return self.poll_get();
+                }
+                None => return self.poll_leader(),
+            };
+            return shared.map(Async::Ready).map_err(Error::Shared);
+        }
+        self.poll_read()
+    }
 }
 
-impl<'a> Put<'a> {
-    fn new(client: &'a Client, key: Key, value: Value) -> Self {
-        Put {
-            client,
-            key,
-            value,
-            cf: None,
-        }
+// The outcome of a coalesced `Get`, handed to every `Get` that waited for it.
+type SharedRead = Result<Option<Value>, Arc<Error>>;
+
+// The senders of the `Get`s waiting on a coalesced read, by column family and key.
+type ReadWaiters = HashMap<(Option<ColumnFamily>, Key), Vec<oneshot::Sender<SharedRead>>>;
+
+impl<'a> Get<'a> {
+    // Whether this `Get` may share a request with others, which is only safe if none
+    // of them asked for anything else than the defaults.
+    fn coalesces(&self) -> bool {
+        let config = &self.client.inner.config;
+        config.coalesce_reads
+            && self.max_staleness.is_none()
+            && self.deadline.is_none()
+            && self.priority == config.default_priority
+            && self.read_policy == ReadPolicy::default()
     }
 
-    pub fn cf(mut self, cf: impl Into<ColumnFamily>) -> Self {
-        self.cf = Some(cf.into());
-        self
+    fn poll_leader(&mut self) -> Poll<Option<Value>, Error> {
+        let shared = match self.poll_read() {
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Ok(Async::Ready(value)) => Ok(value),
+            Err(err) => Err(Arc::new(err)),
+        };
+        self.leader = false;
+        let waiters = self
+            .client
+            .inner
+            .reads_in_flight
+            .lock()
+            .unwrap()
+            .remove(&(self.cf.clone(), self.key.clone()))
+            .unwrap_or_default();
+        for waiter in waiters {
+            let _ = waiter.send(shared.clone());
+        }
+        shared.map(Async::Ready).map_err(Error::Shared)
     }
-}
 
-impl<'a> Future for Put<'a> {
-    type Item = ();
-    type Error = ();
+    fn poll_read(&mut self) -> Poll<Option<Value>, Error> {
+        loop {
+            if let Async::NotReady = self.retry.poll_delay()? {
+                return Ok(Async::NotReady);
+            }
+            match self.send() {
+                Err(err) => {
+                    let key = self.client.encode_key(&self.key);
+                    let region_cache = &self.client.inner.region_cache;
+                    self.retry.on_error(region_cache, &key, err, true)?;
+                }
+                result => return result,
+            }
+        }
+    }
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        let _ = &self.client;
-        let _ = &self.key;
-        let _ = &self.value;
+    fn send(&mut self) -> Poll<Option<Value>, Error> {
+        let key = self.client.encode_key(&self.key);
+        let region = self.client.locate(&key)?;
+        self.instrument.route(&region);
+        #[cfg(feature = "mock")]
+        {
+            if let Some(ref mock) = self.client.inner.mock {
+                if let Async::NotReady = mock.poll_region_request("RawGet", &region)? {
+                    return Ok(Async::NotReady);
+                }
+                return Ok(Async::Ready(mock.get(&self.cf, &key)));
+            }
+        }
+        let _ = region;
+        let _ = key;
         let _ = &self.cf;
+        let _ = &self.priority;
+        let _ = &self.deadline;
+        let _ = &self.read_policy;
+        let _ = &self.max_staleness;
         unimplemented!()
     }
 }
 
-pub struct BatchPut<'a> {
-    client: &'a Client,
-    pairs: Vec<KvPair>,
-    cf: Option<ColumnFamily>,
+impl<'a> Drop for Get<'a> {
+    fn drop(&mut self) {
+        // Dropping the senders wakes the waiters, which then read on their own.
+        if self.leader {
+            self.client
+                .inner
+                .reads_in_flight
+                .lock()
+                .unwrap()
+                .remove(&(self.cf.clone(), self.key.clone()));
+        }
+    }
 }
 
-impl<'a> BatchPut<'a> {
-    fn new(client: &'a Client, pairs: Vec<KvPair>) -> Self {
-        BatchPut {
-            client,
-            pairs,
-            cf: None,
+const DEFAULT_GET_STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+pub struct GetStream<'a> {
+    get: Get<'a>,
+    chunk_size: usize,
+    // The value once read, and how much of it has been yielded.
+    value: Option<Bytes>,
+    offset: usize,
+}
+
+impl<'a> GetStream<'a> {
+    fn new(get: Get<'a>) -> Self {
+        GetStream {
+            get,
+            chunk_size: DEFAULT_GET_STREAM_CHUNK_SIZE,
+            value: None,
+            offset: 0,
         }
     }
 
     pub fn cf(mut self, cf: impl Into<ColumnFamily>) -> Self {
-        self.cf = Some(cf.into());
+        self.get = self.get.cf(cf);
+        self
+    }
+
+    /// Sets the largest chunk yielded. Defaults to 64 KiB.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = cmp::max(chunk_size, 1);
         self
     }
 }
 
-impl<'a> Future for BatchPut<'a> {
-    type Item = ();
-    type Error = ();
+impl<'a> Stream for GetStream<'a> {
+    type Item = Bytes;
+    type Error = Error;
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        let _ = &self.client;
-        let _ = &self.pairs;
-        let _ = &self.cf;
-        unimplemented!()
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if self.value.is_none() {
+            match self.get.poll()? {
+                Async::Ready(value) => {
+                    self.value = Some(value.unwrap_or_default().into_inner().into())
+                }
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+        let value = self.value.as_ref().unwrap();
+        if self.offset >= value.len() {
+            return Ok(Async::Ready(None));
+        }
+        let end = cmp::min(self.offset + self.chunk_size, value.len());
+        let chunk = value.slice(self.offset, end);
+        self.offset = end;
+        Ok(Async::Ready(Some(chunk)))
     }
 }
 
-pub struct Delete<'a> {
+pub struct GetKeyTtl<'a> {
     client: &'a Client,
     key: Key,
     cf: Option<ColumnFamily>,
+    instrument: Instrument,
+    in_flight: Option<InFlight<'a>>,
 }
 
-impl<'a> Delete<'a> {
+impl<'a> GetKeyTtl<'a> {
     fn new(client: &'a Client, key: Key) -> Self {
-        Delete {
+        GetKeyTtl {
             client,
             key,
-            cf: None,
+            cf: client.cf.clone(),
+            instrument: Instrument::default(),
+            in_flight: None,
         }
     }
 
@@ -185,30 +453,50 @@ impl<'a> Delete<'a> {
     }
 }
 
-impl<'a> Future for Delete<'a> {
-    type Item = ();
-    type Error = ();
+impl<'a> Future for GetKeyTtl<'a> {
+    type Item = Option<Duration>;
+    type Error = Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        let _ = &self.client;
-        let _ = &self.key;
+        let _entered = self
+            .instrument
+            .enter("get_key_ttl", self.key.len(), &self.cf);
+        let result = self.poll_ttl();
+        self.instrument.done(result)
+    }
+}
+
+impl<'a> GetKeyTtl<'a> {
+    fn poll_ttl(&mut self) -> Poll<Option<Duration>, Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        #[cfg(feature = "mock")]
+        {
+            if self.client.inner.mock.is_some() {
+                return Ok(Async::Ready(None));
+            }
+        }
+        let _ = self.client.encode_key(&self.key);
         let _ = &self.cf;
         unimplemented!()
     }
 }
 
-pub struct BatchDelete<'a> {
+pub struct BatchGetKeyTtl<'a> {
     client: &'a Client,
     keys: Vec<Key>,
     cf: Option<ColumnFamily>,
+    instrument: Instrument,
+    in_flight: Option<InFlight<'a>>,
 }
 
-impl<'a> BatchDelete<'a> {
+impl<'a> BatchGetKeyTtl<'a> {
     fn new(client: &'a Client, keys: Vec<Key>) -> Self {
-        BatchDelete {
+        BatchGetKeyTtl {
             client,
             keys,
-            cf: None,
+            cf: client.cf.clone(),
+            instrument: Instrument::default(),
+            in_flight: None,
         }
     }
 
@@ -218,42 +506,98 @@ impl<'a> BatchDelete<'a> {
     }
 }
 
-impl<'a> Future for BatchDelete<'a> {
-    type Item = ();
-    type Error = ();
+impl<'a> Future for BatchGetKeyTtl<'a> {
+    type Item = Vec<(Key, Option<Duration>)>;
+    type Error = Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        let _ = &self.client;
-        let _ = &self.keys;
+        let key_len = total_len(&self.keys);
+        let _entered = self
+            .instrument
+            .enter("batch_get_key_ttl", key_len, &self.cf);
+        let result = self.poll_ttls();
+        self.instrument.done(result)
+    }
+}
+
+impl<'a> BatchGetKeyTtl<'a> {
+    fn poll_ttls(&mut self) -> Poll<Vec<(Key, Option<Duration>)>, Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        #[cfg(feature = "mock")]
+        {
+            if self.client.inner.mock.is_some() {
+                let keys = mem::take(&mut self.keys);
+                return Ok(Async::Ready(
+                    keys.into_iter().map(|key| (key, None)).collect(),
+                ));
+            }
+        }
+        for key in &self.keys {
+            let _ = self.client.encode_key(key);
+        }
         let _ = &self.cf;
         unimplemented!()
     }
 }
 
-pub struct Scan<'a> {
+// The key length a batch request is instrumented with.
+fn total_len(keys: &[Key]) -> usize {
+    keys.iter().map(|key| key.len()).sum()
+}
+
+const DEFAULT_BATCH_GET_CHUNK_SIZE: usize = 1024;
+// gRPC's default receive limit, which applies when `Config::max_message_size` is unset.
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
+pub struct BatchGet<'a> {
     client: &'a Client,
-    range: (Key, Key),
-    limit: u32,
-    key_only: bool,
+    keys: Vec<Key>,
     cf: Option<ColumnFamily>,
-    reverse: bool,
+    priority: Priority,
+    read_policy: ReadPolicy,
+    chunk_size: usize,
+    parts: Parts<BatchGetChunk>,
+    // The values found so far, tagged with the positions of their keys.
+    found: Vec<(usize, Value)>,
+    instrument: Instrument,
+    in_flight: Option<InFlight<'a>>,
 }
 
-impl<'a> Scan<'a> {
-    fn new(client: &'a Client, range: (Key, Key), limit: u32) -> Self {
-        Scan {
-            client,
-            range,
-            limit,
-            key_only: false,
-            cf: None,
-            reverse: false,
-        }
+// The encoded keys of one `RawBatchGet` request, along with the position of each in
+// the keys given to `Client::batch_get`. A chunk split again when retried after a
+// region error hands the positions down to its parts.
+struct BatchGetChunk {
+    keys: Vec<Key>,
+    positions: Vec<usize>,
+}
+
+impl BatchGetChunk {
+    // Tags the values returned for this chunk with the positions of their keys, once
+    // per occurrence of a key requested more than once.
+    fn position(&self, pairs: Vec<KvPair>) -> Vec<(usize, Value)> {
+        let values: HashMap<Key, Value> = pairs.into_iter().map(KvPair::into_inner).collect();
+        self.keys
+            .iter()
+            .zip(&self.positions)
+            .filter_map(|(key, position)| values.get(key).map(|value| (*position, value.clone())))
+            .collect()
     }
+}
 
-    pub fn key_only(mut self) -> Self {
-        self.key_only = true;
-        self
+impl<'a> BatchGet<'a> {
+    fn new(client: &'a Client, keys: Vec<Key>) -> Self {
+        BatchGet {
+            client,
+            keys,
+            cf: client.cf.clone(),
+            priority: client.inner.config.default_priority,
+            read_policy: ReadPolicy::default(),
+            chunk_size: DEFAULT_BATCH_GET_CHUNK_SIZE,
+            parts: Parts::new(&client.inner.config),
+            found: Vec::new(),
+            instrument: Instrument::default(),
+            in_flight: None,
+        }
     }
 
     pub fn cf(mut self, cf: impl Into<ColumnFamily>) -> Self {
@@ -261,183 +605,5178 @@ impl<'a> Scan<'a> {
         self
     }
 
-    pub fn reverse(mut self) -> Self {
-        self.reverse = true;
+    /// See `Get::priority`.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
         self
     }
-}
 
-impl<'a> Future for Scan<'a> {
-    type Item = Vec<KvPair>;
-    type Error = ();
+    /// See `Get::read_policy`.
+    pub fn read_policy(mut self, read_policy: ReadPolicy) -> Self {
+        self.read_policy = read_policy;
+        self
+    }
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        let _ = &self.client;
-        let _ = &self.range;
-        let _ = &self.limit;
-        let _ = &self.key_only;
-        let _ = &self.cf;
-        unimplemented!()
+    /// Sets the most keys sent in one `RawBatchGet` request. The keys are grouped by
+    /// region, and each group is split into chunks of at most `chunk_size` keys whose
+    /// total size stays under `Config::max_message_size`; the chunks are sent in
+    /// parallel and their results merged. Defaults to 1024.
+    ///
+    /// Only the request is bounded: the values aren't known in advance, so the
+    /// response to a chunk may still exceed `Config::max_message_size`. Lower
+    /// `chunk_size` when reading large values.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = cmp::max(chunk_size, 1);
+        self
     }
-}
 
-pub struct BatchScan<'a> {
-    client: &'a Client,
-    ranges: Vec<(Key, Key)>,
-    each_limit: u32,
-    key_only: bool,
-    cf: Option<ColumnFamily>,
-    reverse: bool,
-}
+    /// Caps the number of `RawBatchGet` requests in flight at the same time.
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.parts.max_concurrency = cmp::max(max_concurrency, 1);
+        self
+    }
 
-impl<'a> BatchScan<'a> {
-    fn new(client: &'a Client, ranges: Vec<(Key, Key)>, each_limit: u32) -> Self {
-        BatchScan {
-            client,
-            ranges,
-            each_limit,
-            key_only: false,
-            cf: None,
-            reverse: false,
+    /// Resolves to one entry per requested key, in the order the keys were given,
+    /// with `None` for the keys that don't exist. The order holds however the chunks
+    /// complete, including when some of them are retried.
+    pub fn preserve_order(self) -> OrderedBatchGet<'a> {
+        OrderedBatchGet {
+            keys: self.keys.clone(),
+            batch_get: self,
         }
     }
 
-    pub fn key_only(mut self) -> Self {
-        self.key_only = true;
-        self
+    // The requests to send for the encoded `keys`, tagged with their positions. The
+    // byte budget counts the keys only, see `chunk_size`.
+    fn chunks(&self, keys: Vec<(usize, Key)>) -> Result<Vec<BatchGetChunk>, Error> {
+        let max_bytes = self
+            .client
+            .inner
+            .config
+            .max_message_size
+            .unwrap_or(DEFAULT_MAX_MESSAGE_SIZE);
+        let mut chunks = Vec::new();
+        for keys in self.client.group_by_region(keys, |(_, key)| key)? {
+            let mut chunk = BatchGetChunk {
+                keys: Vec::new(),
+                positions: Vec::new(),
+            };
+            let mut bytes = 0;
+            for (position, key) in keys {
+                if !chunk.keys.is_empty()
+                    && (chunk.keys.len() >= self.chunk_size || bytes + key.len() > max_bytes)
+                {
+                    chunks.push(mem::replace(
+                        &mut chunk,
+                        BatchGetChunk {
+                            keys: Vec::new(),
+                            positions: Vec::new(),
+                        },
+                    ));
+                    bytes = 0;
+                }
+                bytes += key.len();
+                chunk.keys.push(key);
+                chunk.positions.push(position);
+            }
+            if !chunk.keys.is_empty() {
+                chunks.push(chunk);
+            }
+        }
+        Ok(chunks)
     }
 
-    pub fn cf(mut self, cf: impl Into<ColumnFamily>) -> Self {
-        self.cf = Some(cf.into());
-        self
+    // Resolves to the values found, each tagged with the position of its key, in the
+    // order the chunks complete.
+    fn poll_positioned(&mut self) -> Poll<Vec<(usize, Value)>, Error> {
+        let key_len = total_len(&self.keys);
+        let _entered = self.instrument.enter("batch_get", key_len, &self.cf);
+        let result = self.poll_found();
+        self.instrument.done(result)
     }
 
-    pub fn reverse(mut self) -> Self {
-        self.reverse = true;
-        self
+    fn poll_found(&mut self) -> Poll<Vec<(usize, Value)>, Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        if !self.parts.started {
+            let client = self.client;
+            let keys = self.keys.iter().map(|key| client.encode_key(key));
+            let chunks = self.chunks(keys.enumerate().collect())?;
+            self.parts.start(chunks);
+        }
+        if let Async::NotReady = self.poll_parts()? {
+            return Ok(Async::NotReady);
+        }
+        Ok(Async::Ready(mem::take(&mut self.found)))
     }
 }
 
-impl<'a> Future for BatchScan<'a> {
-    type Item = Vec<KvPair>;
-    type Error = ();
+impl<'a> Fanout for BatchGet<'a> {
+    type Part = BatchGetChunk;
+    type Output = Vec<KvPair>;
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        let _ = &self.client;
-        let _ = &self.ranges;
-        let _ = &self.each_limit;
-        let _ = &self.key_only;
+    fn parts(&mut self) -> &mut Parts<BatchGetChunk> {
+        &mut self.parts
+    }
+
+    fn send(&self, chunk: &BatchGetChunk) -> Poll<Vec<KvPair>, Error> {
+        let region = self.client.locate(&chunk.keys[0])?;
+        self.instrument.route(&region);
+        #[cfg(feature = "mock")]
+        {
+            if let Some(ref mock) = self.client.inner.mock {
+                let keys = &chunk.keys;
+                if let Async::NotReady = mock.poll_keys_request("RawBatchGet", &region, keys)? {
+                    return Ok(Async::NotReady);
+                }
+                return Ok(Async::Ready(mock.batch_get(&self.cf, &chunk.keys)));
+            }
+        }
+        let _ = region;
+        let _ = chunk;
         let _ = &self.cf;
+        let _ = &self.priority;
+        let _ = &self.read_policy;
         unimplemented!()
     }
-}
-
-pub struct DeleteRange<'a> {
-    client: &'a Client,
-    range: (Key, Key),
-    cf: Option<ColumnFamily>,
-}
 
-impl<'a> DeleteRange<'a> {
-    fn new(client: &'a Client, range: (Key, Key)) -> Self {
-        DeleteRange {
-            client,
-            range,
-            cf: None,
-        }
+    fn done(&mut self, chunk: BatchGetChunk, pairs: Vec<KvPair>) -> Result<bool, Error> {
+        self.found.extend(chunk.position(pairs));
+        Ok(true)
     }
 
-    pub fn cf(mut self, cf: impl Into<ColumnFamily>) -> Self {
-        self.cf = Some(cf.into());
-        self
+    fn failed(&mut self, chunk: BatchGetChunk, err: Error) -> Result<Vec<BatchGetChunk>, Error> {
+        let region_cache = &self.client.inner.region_cache;
+        self.parts
+            .retry
+            .on_error(region_cache, &chunk.keys[0], err, true)?;
+        // Split the keys again along the regions resolved anew.
+        let keys = chunk.positions.into_iter().zip(chunk.keys).collect();
+        self.chunks(keys)
     }
 }
 
-impl<'a> Future for DeleteRange<'a> {
+impl<'a> Future for BatchGet<'a> {
+    type Item = Vec<KvPair>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let values = match self.poll_positioned()? {
+            Async::Ready(values) => values,
+            Async::NotReady => return Ok(Async::NotReady),
+        };
+        let keys = &self.keys;
+        Ok(Async::Ready(
+            values
+                .into_iter()
+                .map(|(position, value)| KvPair::new(keys[position].clone(), value))
+                .collect(),
+        ))
+    }
+}
+
+pub struct OrderedBatchGet<'a> {
+    keys: Vec<Key>,
+    batch_get: BatchGet<'a>,
+}
+
+impl<'a> Future for OrderedBatchGet<'a> {
+    type Item = Vec<(Key, Option<Value>)>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let found = match self.batch_get.poll_positioned()? {
+            Async::Ready(found) => found,
+            Async::NotReady => return Ok(Async::NotReady),
+        };
+        let mut values = vec![None; self.keys.len()];
+        for (position, value) in found {
+            values[position] = Some(value);
+        }
+        let keys = mem::take(&mut self.keys);
+        Ok(Async::Ready(keys.into_iter().zip(values).collect()))
+    }
+}
+
+pub struct Put<'a> {
+    client: &'a Client,
+    key: Key,
+    value: Value,
+    cf: Option<ColumnFamily>,
+    priority: Priority,
+    ttl: Option<Duration>,
+    deadline: Option<Instant>,
+    // See `Get::timeout`.
+    timeout: Deadline,
+    idempotent: bool,
+    idempotency_key: Option<Vec<u8>>,
+    // Whether this `Put` recorded its idempotency key in `Inner::recent_writes`.
+    recorded: bool,
+    error: Option<Error>,
+    retry: Retry,
+    instrument: Instrument,
+    in_flight: Option<InFlight<'a>>,
+}
+
+impl<'a> Put<'a> {
+    fn new(client: &'a Client, key: Key, value: Value) -> Self {
+        Put {
+            error: client
+                .check_key(&key)
+                .and_then(|_| client.check_value(&value, None))
+                .err(),
+            client,
+            key,
+            value,
+            cf: client.cf.clone(),
+            priority: client.inner.config.default_priority,
+            ttl: None,
+            deadline: None,
+            timeout: client.request_timeout(None),
+            idempotent: true,
+            idempotency_key: None,
+            recorded: false,
+            retry: Retry::new(&client.inner.config),
+            instrument: Instrument::default(),
+            in_flight: None,
+        }
+    }
+
+    pub fn cf(mut self, cf: impl Into<ColumnFamily>) -> Self {
+        self.cf = Some(cf.into());
+        self
+    }
+
+    /// See `Get::priority`.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// See `Get::deadline`.
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self.timeout = self.client.request_timeout(self.deadline);
+        self
+    }
+
+    /// Whether the request may be resent according to `Config::request_retry`. Defaults
+    /// to `true`; pass `false` when a duplicated write would be observable, e.g. when
+    /// other writers may update the key in between attempts.
+    pub fn idempotent(mut self, idempotent: bool) -> Self {
+        self.idempotent = idempotent;
+        self
+    }
+
+    /// Tags the write so that another `Put` with the same `key`, e.g. an application
+    /// level retry after an ambiguous timeout, is applied at most once.
+    ///
+    /// The client remembers each idempotency key for 60 seconds after its `Put` was
+    /// sent, and at most 10,000 keys, forgetting the oldest first. Within that window a
+    /// `Put` with a remembered key resolves immediately without being sent if the
+    /// first one was acknowledged, and fails with `Error::WriteInDoubt` if it is still
+    /// in flight or its outcome is unknown, e.g. after `Error::Timeout`. A first `Put`
+    /// that definitely failed is forgotten so that it can be retried. The keys are only
+    /// kept in memory, so this does not protect against duplicates sent by another
+    /// client or after a restart. `fetch_previous` ignores the idempotency key.
+    pub fn idempotency_key(mut self, key: impl Into<Vec<u8>>) -> Self {
+        self.idempotency_key = Some(key.into());
+        self
+    }
+
+    /// Resolves to the value stored before this write, or `None` if the key was absent.
+    /// Like `compare_and_swap`, this requires `api-version = 2` on the TiKV cluster and
+    /// fails with `Error::CasUnsupported` otherwise.
+    pub fn fetch_previous(self) -> PutFetchPrevious<'a> {
+        PutFetchPrevious { put: self }
+    }
+}
+
+impl<'a> Future for Put<'a> {
     type Item = ();
-    type Error = ();
+    type Error = Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        let _ = &self.client;
-        let _ = &self.range;
+        let _entered = self.instrument.enter("put", self.key.len(), &self.cf);
+        let result = self.timeout.check().and_then(|()| self.poll_put());
+        let result = self.timeout.poll(result);
+        self.instrument.done(result)
+    }
+}
+
+impl<'a> Put<'a> {
+    fn poll_put(&mut self) -> Poll<(), Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        let idempotency_key = match self.idempotency_key.clone() {
+            Some(key) => key,
+            None => return self.poll_write(),
+        };
+        let client = self.client;
+        let recent_writes = &client.inner.recent_writes;
+        if !self.recorded {
+            match recent_writes.lock().unwrap().start(&idempotency_key) {
+                Some(true) => return Ok(Async::Ready(())),
+                Some(false) => return Err(Error::WriteInDoubt),
+                None => self.recorded = true,
+            }
+        }
+        let result = self.poll_write();
+        match result {
+            Ok(Async::NotReady) => {}
+            Ok(Async::Ready(())) => recent_writes.lock().unwrap().acknowledge(&idempotency_key),
+            Err(ref err) if is_ambiguous(err) => {}
+            Err(_) => recent_writes.lock().unwrap().forget(&idempotency_key),
+        }
+        result
+    }
+}
+
+impl<'a> Put<'a> {
+    fn poll_write(&mut self) -> Poll<(), Error> {
+        loop {
+            if let Async::NotReady = self.retry.poll_delay()? {
+                return Ok(Async::NotReady);
+            }
+            match self.send() {
+                Err(err) => {
+                    let key = self.client.encode_key(&self.key);
+                    let region_cache = &self.client.inner.region_cache;
+                    self.retry
+                        .on_error(region_cache, &key, err, self.idempotent)?;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn send(&self) -> Poll<(), Error> {
+        let key = self.client.encode_key(&self.key);
+        let region = self.client.locate(&key)?;
+        self.instrument.route(&region);
+        #[cfg(feature = "mock")]
+        {
+            if let Some(ref mock) = self.client.inner.mock {
+                if let Async::NotReady = mock.poll_region_request("RawPut", &region)? {
+                    return Ok(Async::NotReady);
+                }
+                mock.put(&self.cf, key, self.value.clone());
+                return Ok(Async::Ready(()));
+            }
+        }
+        let _ = region;
+        let _ = key;
+        let _ = &self.value;
         let _ = &self.cf;
+        let _ = &self.priority;
+        let _ = &self.ttl;
+        let _ = &self.deadline;
         unimplemented!()
     }
 }
 
-pub struct Connect {
-    config: Config,
+// Whether a write that failed with `err` may still have been applied.
+fn is_ambiguous(err: &Error) -> bool {
+    match *err {
+        Error::Timeout | Error::Canceled(_) => true,
+        _ => err
+            .grpc_status()
+            .is_some_and(|status| status.status == ::grpc::RpcStatusCode::DeadlineExceeded),
+    }
 }
 
-impl Connect {
-    fn new(config: Config) -> Self {
-        Connect { config }
+const IDEMPOTENCY_WINDOW: Duration = Duration::from_secs(60);
+const MAX_IDEMPOTENCY_KEYS: usize = 10_000;
+
+// The idempotency keys of recent `Put`s; see `Put::idempotency_key`.
+#[derive(Default)]
+struct RecentWrites {
+    writes: HashMap<Vec<u8>, RecentWrite>,
+}
+
+struct RecentWrite {
+    started: Instant,
+    acknowledged: bool,
+}
+
+impl RecentWrites {
+    // Records the start of a write tagged with `key`, unless one was recorded within
+    // the window, in which case it returns whether that one was acknowledged.
+    fn start(&mut self, key: &[u8]) -> Option<bool> {
+        if let Some(write) = self.writes.get(key) {
+            if write.started.elapsed() < IDEMPOTENCY_WINDOW {
+                return Some(write.acknowledged);
+            }
+        }
+        if self.writes.len() >= MAX_IDEMPOTENCY_KEYS {
+            self.writes
+                .retain(|_, write| write.started.elapsed() < IDEMPOTENCY_WINDOW);
+        }
+        if self.writes.len() >= MAX_IDEMPOTENCY_KEYS {
+            let oldest = self
+                .writes
+                .iter()
+                .min_by_key(|(_, write)| write.started)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.writes.remove(&oldest);
+            }
+        }
+        self.writes.insert(
+            key.to_vec(),
+            RecentWrite {
+                started: Instant::now(),
+                acknowledged: false,
+            },
+        );
+        None
+    }
+
+    fn acknowledge(&mut self, key: &[u8]) {
+        if let Some(write) = self.writes.get_mut(key) {
+            write.acknowledged = true;
+        }
+    }
+
+    fn forget(&mut self, key: &[u8]) {
+        self.writes.remove(key);
     }
 }
 
-impl Future for Connect {
-    type Item = Client;
+pub struct PutFetchPrevious<'a> {
+    put: Put<'a>,
+}
+
+impl<'a> Future for PutFetchPrevious<'a> {
+    type Item = Option<Value>;
     type Error = Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        let _config = &self.config;
+        let put = &mut self.put;
+        let _entered = put
+            .instrument
+            .enter("put_fetch_previous", put.key.len(), &put.cf);
+        let result = self.poll_put();
+        self.put.instrument.done(result)
+    }
+}
+
+impl<'a> PutFetchPrevious<'a> {
+    fn poll_put(&mut self) -> Poll<Option<Value>, Error> {
+        self.put.client.check_open(&mut self.put.in_flight)?;
+        if let Some(err) = self.put.error.take() {
+            return Err(err);
+        }
+        #[cfg(feature = "mock")]
+        {
+            let put = &self.put;
+            if let Some(ref mock) = put.client.inner.mock {
+                let key = put.client.encode_key(&put.key);
+                return Ok(Async::Ready(mock.put(&put.cf, key, put.value.clone())));
+            }
+        }
+        let _ = self.put.client.encode_key(&self.put.key);
+        let _ = &self.put;
         unimplemented!()
     }
 }
 
-pub struct Client;
+const DEFAULT_MAX_CONCURRENCY: usize = 16;
 
-impl Client {
-    #![cfg_attr(feature = "cargo-clippy", allow(new_ret_no_self))]
-    pub fn new(config: &Config) -> Connect {
-        Connect::new(config.clone())
+// The parts of a request split into several, e.g. one per region, that are sent
+// concurrently, at most `max_concurrency` at a time. The attempts of all the parts
+// count against the same `retry`.
+struct Parts<P> {
+    pending: VecDeque<P>,
+    in_flight: Vec<P>,
+    max_concurrency: usize,
+    retry: Retry,
+    started: bool,
+}
+
+impl<P> Parts<P> {
+    fn new(config: &Config) -> Self {
+        Parts {
+            pending: VecDeque::new(),
+            in_flight: Vec::new(),
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            retry: Retry::new(config),
+            started: false,
+        }
     }
 
-    pub fn get(&self, key: impl AsRef<Key>) -> Get {
-        Get::new(self, key.as_ref().clone())
+    fn start(&mut self, parts: Vec<P>) {
+        self.pending.extend(parts);
+        self.started = true;
     }
+}
 
-    pub fn batch_get(&self, keys: impl AsRef<[Key]>) -> BatchGet {
-        BatchGet::new(self, keys.as_ref().to_vec())
+// A request sent as `Parts`.
+trait Fanout {
+    type Part;
+    type Output;
+
+    fn parts(&mut self) -> &mut Parts<Self::Part>;
+
+    // Sends an attempt of `part`.
+    fn send(&self, part: &Self::Part) -> Poll<Self::Output, Error>;
+
+    // Takes in the output of `part`, which completed, returning whether the request
+    // goes on: if not, it resolves right away and the parts left are dropped.
+    fn done(&mut self, part: Self::Part, output: Self::Output) -> Result<bool, Error>;
+
+    // Handles `err`, the failure of an attempt of `part`, returning the parts to
+    // send in its place, e.g. `part` split along the regions resolved anew.
+    fn failed(&mut self, part: Self::Part, err: Error) -> Result<Vec<Self::Part>, Error>;
+
+    // Resolves once every part has completed, failing with the first error that
+    // isn't retried.
+    fn poll_parts(&mut self) -> Poll<(), Error> {
+        loop {
+            if let Async::NotReady = self.parts().retry.poll_delay()? {
+                return Ok(Async::NotReady);
+            }
+            let in_flight = {
+                let parts = self.parts();
+                while parts.in_flight.len() < parts.max_concurrency {
+                    match parts.pending.pop_front() {
+                        Some(part) => parts.in_flight.push(part),
+                        None => break,
+                    }
+                }
+                mem::take(&mut parts.in_flight)
+            };
+            if in_flight.is_empty() {
+                return Ok(Async::Ready(()));
+            }
+            let mut progress = false;
+            for part in in_flight {
+                match self.send(&part) {
+                    Ok(Async::Ready(output)) => {
+                        if !self.done(part, output)? {
+                            return Ok(Async::Ready(()));
+                        }
+                        progress = true;
+                    }
+                    Ok(Async::NotReady) => self.parts().in_flight.push(part),
+                    Err(err) => {
+                        let retried = self.failed(part, err)?;
+                        self.parts().pending.extend(retried);
+                        progress = true;
+                    }
+                }
+            }
+            if !progress {
+                return Ok(Async::NotReady);
+            }
+        }
     }
+}
 
-    pub fn put(&self, key: impl Into<Key>, value: impl Into<Value>) -> Put {
-        Put::new(self, key.into(), value.into())
+pub struct BatchPut<'a> {
+    client: &'a Client,
+    pairs: Vec<KvPair>,
+    cf: Option<ColumnFamily>,
+    priority: Priority,
+    ttl: Option<Duration>,
+    error: Option<Error>,
+    // One part per region, of encoded pairs.
+    parts: Parts<Vec<KvPair>>,
+    instrument: Instrument,
+    in_flight: Option<InFlight<'a>>,
+}
+
+impl<'a> BatchPut<'a> {
+    fn new(client: &'a Client, pairs: Vec<KvPair>) -> Self {
+        BatchPut {
+            error: pairs
+                .iter()
+                .enumerate()
+                .filter_map(|(i, pair)| client.check_value(pair.value(), Some(i)).err())
+                .next(),
+            client,
+            pairs: Self::dedup(pairs),
+            cf: client.cf.clone(),
+            priority: client.inner.config.default_priority,
+            ttl: None,
+            parts: Parts::new(&client.inner.config),
+            instrument: Instrument::default(),
+            in_flight: None,
+        }
     }
 
-    pub fn batch_put(&self, pairs: impl IntoIterator<Item = impl Into<KvPair>>) -> BatchPut {
-        BatchPut::new(self, pairs.into_iter().map(Into::into).collect())
+    pub fn cf(mut self, cf: impl Into<ColumnFamily>) -> Self {
+        self.cf = Some(cf.into());
+        self
     }
 
-    pub fn delete(&self, key: impl AsRef<Key>) -> Delete {
-        Delete::new(self, key.as_ref().clone())
+    /// See `Get::priority`.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
     }
 
-    pub fn batch_delete(&self, keys: impl AsRef<[Key]>) -> BatchDelete {
-        BatchDelete::new(self, keys.as_ref().to_vec())
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
     }
 
-    pub fn scan(&self, range: impl RangeBounds<Key>, limit: u32) -> Scan {
-        Scan::new(self, Self::extract_range(&range), limit)
+    /// Caps the number of `RawBatchPut` requests in flight at the same time.
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.parts.max_concurrency = cmp::max(max_concurrency, 1);
+        self
     }
 
-    pub fn batch_scan<Ranges, Bounds>(&self, ranges: Ranges, each_limit: u32) -> BatchScan
-    where
-        Ranges: AsRef<[Bounds]>,
-        Bounds: RangeBounds<Key>,
-    {
-        BatchScan::new(
-            self,
-            ranges.as_ref().iter().map(Self::extract_range).collect(),
-            each_limit,
-        )
+    // Keeps only the last pair of each key, so that the order the per-region requests
+    // are applied in can't change the outcome.
+    fn dedup(pairs: Vec<KvPair>) -> Vec<KvPair> {
+        let mut seen = HashSet::with_capacity(pairs.len());
+        let mut pairs: Vec<KvPair> = pairs
+            .into_iter()
+            .rev()
+            .filter(|pair| seen.insert(pair.key().clone()))
+            .collect();
+        pairs.reverse();
+        pairs
+    }
+}
+
+impl<'a> Future for BatchPut<'a> {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let key_len = self.pairs.iter().map(|pair| pair.key().len()).sum();
+        let _entered = self.instrument.enter("batch_put", key_len, &self.cf);
+        let result = self.poll_put();
+        self.instrument.done(result)
+    }
+}
+
+impl<'a> BatchPut<'a> {
+    fn poll_put(&mut self) -> Poll<(), Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        if !self.parts.started {
+            let client = self.client;
+            let pairs = self
+                .pairs
+                .iter()
+                .map(|pair| KvPair::new(client.encode_key(pair.key()), pair.value().clone()))
+                .collect();
+            let groups = client.group_by_region(pairs, KvPair::key)?;
+            self.parts.start(groups);
+        }
+        self.poll_parts()
     }
+}
 
-    pub fn delete_range(&self, range: impl RangeBounds<Key>) -> DeleteRange {
-        DeleteRange::new(self, Self::extract_range(&range))
+impl<'a> Fanout for BatchPut<'a> {
+    type Part = Vec<KvPair>;
+    type Output = ();
+
+    fn parts(&mut self) -> &mut Parts<Vec<KvPair>> {
+        &mut self.parts
     }
 
-    fn extract_range(_range: &impl RangeBounds<Key>) -> (Key, Key) {
+    fn send(&self, pairs: &Vec<KvPair>) -> Poll<(), Error> {
+        let region = self.client.locate(pairs[0].key())?;
+        self.instrument.route(&region);
+        #[cfg(feature = "mock")]
+        {
+            if let Some(ref mock) = self.client.inner.mock {
+                let keys = pairs.iter().map(KvPair::key);
+                if let Async::NotReady = mock.poll_keys_request("RawBatchPut", &region, keys)? {
+                    return Ok(Async::NotReady);
+                }
+                mock.batch_put(&self.cf, pairs);
+                return Ok(Async::Ready(()));
+            }
+        }
+        let _ = region;
+        let _ = pairs;
+        let _ = &self.cf;
+        let _ = &self.priority;
+        let _ = &self.ttl;
         unimplemented!()
     }
+
+    fn done(&mut self, _: Vec<KvPair>, _: ()) -> Result<bool, Error> {
+        Ok(true)
+    }
+
+    fn failed(&mut self, pairs: Vec<KvPair>, err: Error) -> Result<Vec<Vec<KvPair>>, Error> {
+        let region_cache = &self.client.inner.region_cache;
+        self.parts
+            .retry
+            .on_error(region_cache, pairs[0].key(), err, true)?;
+        self.client.group_by_region(pairs, KvPair::key)
+    }
+}
+
+const DEFAULT_PUT_SINK_MAX_PAIRS: usize = 1024;
+const DEFAULT_PUT_SINK_MAX_BYTES: usize = 1024 * 1024;
+const DEFAULT_PUT_SINK_MAX_DELAY: Duration = Duration::from_millis(10);
+
+/// A `Sink` that buffers pairs and writes them as `BatchPut`s, which group them by
+/// region.
+///
+/// The buffer is sent once it holds `max_pairs` pairs or `max_bytes` of keys and
+/// values, or once its first pair has waited `max_delay`; there is no timer, so the
+/// delay is checked as pairs arrive and `poll_complete` must be called to send a
+/// partial batch during a lull. Only one batch is in flight at a time: while it is
+/// outstanding and the buffer is full, `start_send` refuses new pairs, which pushes
+/// back on the producer when the cluster is slow. `close` sends whatever is buffered
+/// and resolves once every batch has been written.
+///
+/// A batch that fails is put back at the front of the buffer before its error is
+/// returned, along with the pair being sent if any, so no pair is lost: polling the
+/// sink again resends them, and dropping it discards them.
+pub struct PutSink<'a> {
+    client: &'a Client,
+    cf: Option<ColumnFamily>,
+    pairs: Vec<KvPair>,
+    bytes: usize,
+    batch_started: Option<Instant>,
+    in_flight: Option<BatchPut<'a>>,
+    max_pairs: usize,
+    max_bytes: usize,
+    max_delay: Duration,
+}
+
+impl<'a> PutSink<'a> {
+    fn new(client: &'a Client) -> Self {
+        PutSink {
+            client,
+            cf: client.cf.clone(),
+            pairs: Vec::new(),
+            bytes: 0,
+            batch_started: None,
+            in_flight: None,
+            max_pairs: DEFAULT_PUT_SINK_MAX_PAIRS,
+            max_bytes: DEFAULT_PUT_SINK_MAX_BYTES,
+            max_delay: DEFAULT_PUT_SINK_MAX_DELAY,
+        }
+    }
+
+    pub fn cf(mut self, cf: impl Into<ColumnFamily>) -> Self {
+        self.cf = Some(cf.into());
+        self
+    }
+
+    /// Defaults to 1024.
+    pub fn max_pairs(mut self, max_pairs: usize) -> Self {
+        self.max_pairs = cmp::max(max_pairs, 1);
+        self
+    }
+
+    /// Defaults to 1 MiB.
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Defaults to 10ms.
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    fn is_full(&self) -> bool {
+        self.pairs.len() >= self.max_pairs
+            || self.bytes >= self.max_bytes
+            || self
+                .batch_started
+                .is_some_and(|started| started.elapsed() >= self.max_delay)
+    }
+
+    fn buffer(&mut self, pair: KvPair) {
+        if self.pairs.is_empty() {
+            self.batch_started = Some(Instant::now());
+        }
+        self.bytes += pair.key().len() + pair.value().len();
+        self.pairs.push(pair);
+    }
+
+    // Puts the pairs of a failed batch back in front of those buffered since.
+    fn restore(&mut self, put: BatchPut<'a>) {
+        let buffered = mem::replace(&mut self.pairs, put.pairs);
+        self.pairs.extend(buffered);
+        self.bytes = self
+            .pairs
+            .iter()
+            .map(|pair| pair.key().len() + pair.value().len())
+            .sum();
+        self.batch_started = Some(Instant::now());
+    }
+
+    // Waits for the batch in flight, then sends the buffered pairs as the next one.
+    fn send_buffered(&mut self) -> Poll<(), Error> {
+        let polled = match self.in_flight {
+            Some(ref mut put) => put.poll(),
+            None => Ok(Async::Ready(())),
+        };
+        match polled {
+            Ok(Async::Ready(())) => self.in_flight = None,
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Err(err) => {
+                let put = self.in_flight.take().unwrap();
+                self.restore(put);
+                return Err(err);
+            }
+        }
+        if !self.pairs.is_empty() {
+            let mut put = BatchPut::new(self.client, mem::take(&mut self.pairs));
+            put.cf = self.cf.clone();
+            self.bytes = 0;
+            self.batch_started = None;
+            match put.poll() {
+                Ok(Async::Ready(())) => {}
+                Ok(Async::NotReady) => self.in_flight = Some(put),
+                Err(err) => {
+                    self.restore(put);
+                    return Err(err);
+                }
+            }
+        }
+        Ok(Async::Ready(()))
+    }
+}
+
+impl<'a> Sink for PutSink<'a> {
+    type SinkItem = KvPair;
+    type SinkError = Error;
+
+    fn start_send(&mut self, pair: KvPair) -> StartSend<KvPair, Error> {
+        if self.is_full() {
+            match self.send_buffered() {
+                Ok(Async::Ready(())) => {}
+                Ok(Async::NotReady) => return Ok(AsyncSink::NotReady(pair)),
+                Err(err) => {
+                    self.buffer(pair);
+                    return Err(err);
+                }
+            }
+        }
+        self.buffer(pair);
+        if self.is_full() {
+            self.send_buffered()?;
+        }
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Error> {
+        while self.in_flight.is_some() || !self.pairs.is_empty() {
+            if let Async::NotReady = self.send_buffered()? {
+                return Ok(Async::NotReady);
+            }
+        }
+        Ok(Async::Ready(()))
+    }
+
+    fn close(&mut self) -> Poll<(), Error> {
+        self.poll_complete()
+    }
+}
+
+pub struct Delete<'a> {
+    client: &'a Client,
+    key: Key,
+    cf: Option<ColumnFamily>,
+    priority: Priority,
+    deadline: Option<Instant>,
+    // See `Get::timeout`.
+    timeout: Deadline,
+    idempotent: bool,
+    error: Option<Error>,
+    retry: Retry,
+    instrument: Instrument,
+    in_flight: Option<InFlight<'a>>,
+}
+
+impl<'a> Delete<'a> {
+    fn new(client: &'a Client, key: Key) -> Self {
+        Delete {
+            error: client.check_key(&key).err(),
+            client,
+            key,
+            cf: client.cf.clone(),
+            priority: client.inner.config.default_priority,
+            deadline: None,
+            timeout: client.request_timeout(None),
+            idempotent: true,
+            retry: Retry::new(&client.inner.config),
+            instrument: Instrument::default(),
+            in_flight: None,
+        }
+    }
+
+    pub fn cf(mut self, cf: impl Into<ColumnFamily>) -> Self {
+        self.cf = Some(cf.into());
+        self
+    }
+
+    /// See `Get::priority`.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Resolves to whether the key existed before it was deleted.
+    ///
+    /// `RawDelete` doesn't report this, so the key is read with a `RawGet` before it
+    /// is deleted, and a write in between may be misreported.
+    pub fn report_existence(self) -> ReportingDelete<'a> {
+        let mut get = Get::new(self.client, self.key.clone());
+        get.cf = self.cf.clone();
+        get.priority = self.priority;
+        if let Some(deadline) = self.deadline {
+            get = get.deadline(deadline);
+        }
+        ReportingDelete {
+            get,
+            existed: None,
+            delete: self,
+        }
+    }
+
+    /// See `Get::deadline`.
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self.timeout = self.client.request_timeout(self.deadline);
+        self
+    }
+
+    /// See `Put::idempotent`.
+    pub fn idempotent(mut self, idempotent: bool) -> Self {
+        self.idempotent = idempotent;
+        self
+    }
+}
+
+impl<'a> Future for Delete<'a> {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let _entered = self.instrument.enter("delete", self.key.len(), &self.cf);
+        let result = self.timeout.check().and_then(|()| self.poll_delete());
+        let result = self.timeout.poll(result);
+        self.instrument.done(result)
+    }
+}
+
+impl<'a> Delete<'a> {
+    fn poll_delete(&mut self) -> Poll<(), Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        loop {
+            if let Async::NotReady = self.retry.poll_delay()? {
+                return Ok(Async::NotReady);
+            }
+            match self.send() {
+                Err(err) => {
+                    let key = self.client.encode_key(&self.key);
+                    let region_cache = &self.client.inner.region_cache;
+                    self.retry
+                        .on_error(region_cache, &key, err, self.idempotent)?;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn send(&self) -> Poll<(), Error> {
+        let key = self.client.encode_key(&self.key);
+        let region = self.client.locate(&key)?;
+        self.instrument.route(&region);
+        #[cfg(feature = "mock")]
+        {
+            if let Some(ref mock) = self.client.inner.mock {
+                if let Async::NotReady = mock.poll_region_request("RawDelete", &region)? {
+                    return Ok(Async::NotReady);
+                }
+                mock.delete(&self.cf, &key);
+                return Ok(Async::Ready(()));
+            }
+        }
+        let _ = region;
+        let _ = key;
+        let _ = &self.cf;
+        let _ = &self.priority;
+        let _ = &self.deadline;
+        unimplemented!()
+    }
+}
+
+pub struct ReportingDelete<'a> {
+    get: Get<'a>,
+    existed: Option<bool>,
+    delete: Delete<'a>,
+}
+
+impl<'a> Future for ReportingDelete<'a> {
+    type Item = bool;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if self.existed.is_none() {
+            let value = match self.get.poll()? {
+                Async::Ready(value) => value,
+                Async::NotReady => return Ok(Async::NotReady),
+            };
+            self.existed = Some(value.is_some());
+        }
+        match self.delete.poll()? {
+            Async::Ready(()) => Ok(Async::Ready(self.existed.unwrap())),
+            Async::NotReady => Ok(Async::NotReady),
+        }
+    }
+}
+
+pub struct BatchDelete<'a> {
+    client: &'a Client,
+    keys: Vec<Key>,
+    cf: Option<ColumnFamily>,
+    priority: Priority,
+    retry: Retry,
+    instrument: Instrument,
+    in_flight: Option<InFlight<'a>>,
+}
+
+impl<'a> BatchDelete<'a> {
+    fn new(client: &'a Client, keys: Vec<Key>) -> Self {
+        BatchDelete {
+            client,
+            keys,
+            cf: client.cf.clone(),
+            priority: client.inner.config.default_priority,
+            retry: Retry::new(&client.inner.config),
+            instrument: Instrument::default(),
+            in_flight: None,
+        }
+    }
+
+    pub fn cf(mut self, cf: impl Into<ColumnFamily>) -> Self {
+        self.cf = Some(cf.into());
+        self
+    }
+
+    /// See `Get::priority`.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Resolves to whether each key existed before it was deleted, in the order the
+    /// keys were given.
+    ///
+    /// Like `Delete::report_existence`, this reads the keys with a batch get before
+    /// deleting them, so a key written concurrently in between may be misreported.
+    pub fn report_existence(self) -> ReportingBatchDelete<'a> {
+        let mut batch_get = BatchGet::new(self.client, self.keys.clone());
+        batch_get.cf = self.cf.clone();
+        batch_get.priority = self.priority;
+        ReportingBatchDelete {
+            batch_get: batch_get.preserve_order(),
+            existence: None,
+            batch_delete: self,
+        }
+    }
+}
+
+impl<'a> Future for BatchDelete<'a> {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let key_len = total_len(&self.keys);
+        let _entered = self.instrument.enter("batch_delete", key_len, &self.cf);
+        let result = self.poll_delete();
+        self.instrument.done(result)
+    }
+}
+
+impl<'a> BatchDelete<'a> {
+    fn poll_delete(&mut self) -> Poll<(), Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        loop {
+            if let Async::NotReady = self.retry.poll_delay()? {
+                return Ok(Async::NotReady);
+            }
+            match self.send() {
+                Err(err) => {
+                    let key = self.keys.first().map(|key| self.client.encode_key(key));
+                    let region_cache = &self.client.inner.region_cache;
+                    self.retry
+                        .on_error(region_cache, &key.unwrap_or_default(), err, true)?;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn send(&self) -> Poll<(), Error> {
+        let keys: Vec<Key> = self
+            .keys
+            .iter()
+            .map(|key| self.client.encode_key(key))
+            .collect();
+        let region = self
+            .client
+            .locate(&keys.first().cloned().unwrap_or_default())?;
+        self.instrument.route(&region);
+        #[cfg(feature = "mock")]
+        {
+            if let Some(ref mock) = self.client.inner.mock {
+                if let Async::NotReady = mock.poll_region_request("RawBatchDelete", &region)? {
+                    return Ok(Async::NotReady);
+                }
+                mock.batch_delete(&self.cf, &keys);
+                return Ok(Async::Ready(()));
+            }
+        }
+        let _ = region;
+        let _ = keys;
+        let _ = &self.cf;
+        let _ = &self.priority;
+        unimplemented!()
+    }
+}
+
+pub struct ReportingBatchDelete<'a> {
+    batch_get: OrderedBatchGet<'a>,
+    existence: Option<Vec<(Key, bool)>>,
+    batch_delete: BatchDelete<'a>,
+}
+
+impl<'a> Future for ReportingBatchDelete<'a> {
+    type Item = Vec<(Key, bool)>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if self.existence.is_none() {
+            let values = match self.batch_get.poll()? {
+                Async::Ready(values) => values,
+                Async::NotReady => return Ok(Async::NotReady),
+            };
+            self.existence = Some(
+                values
+                    .into_iter()
+                    .map(|(key, value)| (key, value.is_some()))
+                    .collect(),
+            );
+        }
+        match self.batch_delete.poll()? {
+            Async::Ready(()) => Ok(Async::Ready(self.existence.take().unwrap())),
+            Async::NotReady => Ok(Async::NotReady),
+        }
+    }
+}
+
+const MAX_SCAN_SIZE_HINT: usize = 64 * 1024;
+
+// The predicate set by `Scan::take_while`.
+type TakeWhile<'a> = Box<FnMut(&KvPair) -> bool + Send + 'a>;
+
+// The transform set by `Scan::map_value`.
+type MapValue<'a> = Box<Fn(Value) -> Result<Value, Error> + Send + 'a>;
+
+pub struct Scan<'a> {
+    client: &'a Client,
+    range: (Key, Key),
+    limit: u32,
+    key_only: bool,
+    cf: Option<ColumnFamily>,
+    priority: Priority,
+    reverse: bool,
+    deadline: Option<Instant>,
+    // See `Get::timeout`.
+    timeout: Deadline,
+    read_policy: ReadPolicy,
+    max_staleness: Option<Duration>,
+    filter_prefix: Option<Key>,
+    // Set by `unordered_parallel`.
+    max_concurrency: Option<usize>,
+    take_while: Option<TakeWhile<'a>>,
+    map_value: Option<MapValue<'a>>,
+    size_hint: Option<usize>,
+    // Set by `Client::scan_from`, so that `reverse` scans down from this key.
+    start: Option<Key>,
+    // Set by `max_bytes`, with the value bytes gathered so far and whether the
+    // budget ended the scan.
+    max_bytes: Option<usize>,
+    bytes: usize,
+    over_budget: bool,
+    // Set by `with_region_markers`: the position in the result of the first pair of
+    // each region response, with the id of the region that sent it.
+    region_starts: Option<Vec<(usize, u64)>>,
+    // The encoded key the next region is scanned from, the lower bound of the rest of
+    // the range, or its upper bound when reversed, and the pairs gathered from the
+    // regions scanned so far.
+    cursor: Option<Key>,
+    gathered: Vec<KvPair>,
+    // The encoded parts of the range, one per region, of an `unordered_parallel`
+    // scan.
+    parallel: Option<Parts<(Key, Key)>>,
+    // The pairs returned by TiKV so far, before `take_while` and the byte budget,
+    // and whether `take_while` ended the scan.
+    received: usize,
+    ended: bool,
+    retry: Retry,
+    instrument: Instrument,
+    in_flight: Option<InFlight<'a>>,
+}
+
+impl<'a> Scan<'a> {
+    fn new(client: &'a Client, range: (Key, Key), limit: u32) -> Self {
+        Scan {
+            client,
+            range,
+            limit,
+            key_only: false,
+            cf: client.cf.clone(),
+            priority: client.inner.config.default_priority,
+            reverse: false,
+            deadline: None,
+            timeout: client.request_timeout(None),
+            read_policy: ReadPolicy::default(),
+            max_staleness: None,
+            filter_prefix: None,
+            max_concurrency: None,
+            take_while: None,
+            map_value: None,
+            size_hint: None,
+            start: None,
+            max_bytes: None,
+            bytes: 0,
+            over_budget: false,
+            region_starts: None,
+            cursor: None,
+            gathered: Vec::new(),
+            parallel: None,
+            received: 0,
+            ended: false,
+            retry: Retry::new(&client.inner.config),
+            instrument: Instrument::default(),
+            in_flight: None,
+        }
+    }
+
+    fn from_key(client: &'a Client, start: Key, limit: u32) -> Self {
+        let mut scan = Scan::new(client, (start.clone(), Key::default()), limit);
+        scan.start = Some(start);
+        scan
+    }
+
+    #[deprecated(note = "use `Client::scan_keys` instead")]
+    pub fn key_only(mut self) -> Self {
+        self.key_only = true;
+        self
+    }
+
+    pub fn cf(mut self, cf: impl Into<ColumnFamily>) -> Self {
+        self.cf = Some(cf.into());
+        self
+    }
+
+    /// See `Get::priority`.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Scans in descending key order. The range keeps its meaning, only the order
+    /// changes: `a..z` reversed returns the keys below `z` down to and including
+    /// `a`, and `a..=z` reversed starts at `z` itself. A scan created by
+    /// `Client::scan_from` covers the keys up to and including its start key instead.
+    pub fn reverse(mut self) -> Self {
+        self.reverse = true;
+        if let Some(start) = self.start.take() {
+            self.range = (Key::default(), start.next());
+        }
+        self
+    }
+
+    // The `start_key` and `end_key` of the `RawScan` request for the encoded `range`.
+    // A reverse scan starts from its exclusive upper bound and stops at its inclusive
+    // lower bound, so the half-open range is swapped rather than having its ends
+    // adjusted; an inclusive `..=z` was already turned into the exclusive `..z\0` by
+    // `extract_range`.
+    fn request_bounds(&self, range: &(Key, Key)) -> (Key, Key) {
+        let (start, end) = range.clone();
+        if self.reverse {
+            (end, start)
+        } else {
+            (start, end)
+        }
+    }
+
+    /// Turns the scan into a stream that fetches the range lazily, one batch at a
+    /// time, as the consumer polls it. `Config::max_scan_limit` doesn't apply.
+    pub fn into_stream(self) -> ScanStream<'a> {
+        ScanStream::new(self)
+    }
+
+    /// See `Get::deadline`.
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self.timeout = self.client.request_timeout(self.deadline);
+        self
+    }
+
+    /// See `Get::read_policy`.
+    pub fn read_policy(mut self, read_policy: ReadPolicy) -> Self {
+        self.read_policy = read_policy;
+        self
+    }
+
+    /// See `Get::stale`. Every region of the range is read as of the same timestamp,
+    /// but a region whose replica falls behind is read from its leader at that
+    /// timestamp too, so the scan stays a consistent snapshot of that past point.
+    pub fn stale(mut self, max_staleness: Duration) -> Self {
+        self.max_staleness = Some(max_staleness);
+        self.read_policy = ReadPolicy::ClosestReplica;
+        self
+    }
+
+    /// Only returns the keys in the range that start with `prefix`; `limit` counts
+    /// matching keys only.
+    ///
+    /// The filter is pushed down to TiKV through the coprocessor when it supports it,
+    /// so that non-matching pairs aren't sent back. Otherwise they are transferred
+    /// and dropped by the client, and the scan uses as much bandwidth as an
+    /// unfiltered one.
+    pub fn filter_prefix(mut self, prefix: impl Into<Key>) -> Self {
+        self.filter_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Resolves every region of the range up front and scans up to `max_concurrency`
+    /// of them at the same time, instead of one after the other.
+    ///
+    /// The pairs are NOT in key order: each region's pairs are appended as its scan
+    /// completes. When `limit` is hit, which pairs of the range are returned is
+    /// unspecified, and `reverse` has no effect. Meant for wide bounded ranges whose
+    /// consumer doesn't care about order.
+    pub fn unordered_parallel(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = Some(cmp::max(max_concurrency, 1));
+        self
+    }
+
+    /// Ends the scan at the first pair for which `predicate` returns `false`, which is
+    /// left out of the result, on top of `limit`. The predicate runs on the client as
+    /// the pairs arrive, so the batch holding that pair has already been read, but no
+    /// further batches or regions are requested.
+    pub fn take_while(mut self, predicate: impl FnMut(&KvPair) -> bool + Send + 'a) -> Self {
+        self.take_while = Some(Box::new(predicate));
+        self
+    }
+
+    /// Replaces each value returned with `transform(value)`, e.g. to decompress or
+    /// decode it, as the pairs arrive. It runs before `take_while`, which sees the
+    /// transformed pairs, and not at all on a key-only scan. The first error it
+    /// returns fails the scan with an `Error::MapValue` naming the key.
+    pub fn map_value(
+        mut self,
+        transform: impl Fn(Value) -> Result<Value, Error> + Send + 'a,
+    ) -> Self {
+        self.map_value = Some(Box::new(transform));
+        self
+    }
+
+    /// Reserves room for about `expected` pairs in the result up front, to save
+    /// reallocations when the caller has a good estimate. This is purely an allocation
+    /// hint: it doesn't change which pairs are returned. The reservation never exceeds
+    /// `limit`, nor 65,536 pairs, so a bogus hint can't exhaust memory.
+    pub fn size_hint(mut self, expected: usize) -> Self {
+        self.size_hint = Some(expected);
+        self
+    }
+
+    // The capacity to allocate the result with, for a scan running with `limit`.
+    fn result_capacity(&self, limit: u32) -> usize {
+        let hint = self.size_hint.unwrap_or(0);
+        cmp::min(hint, cmp::min(limit as usize, MAX_SCAN_SIZE_HINT))
+    }
+
+    // Appends a batch of pairs to `result` as it arrives, applying `map_value`,
+    // `take_while` and the `max_bytes` budget. Returns whether the scan goes on past
+    // this batch.
+    fn assemble(&mut self, pairs: Vec<KvPair>, result: &mut Vec<KvPair>) -> Result<bool, Error> {
+        self.received += pairs.len();
+        for mut pair in pairs {
+            if !self.key_only {
+                if let Some(ref transform) = self.map_value {
+                    let value = mem::take(&mut pair.1);
+                    pair.1 = transform(value).map_err(|err| Error::MapValue {
+                        key: pair.0.clone(),
+                        err: Box::new(err),
+                    })?;
+                }
+            }
+            if let Some(ref mut predicate) = self.take_while {
+                if !predicate(&pair) {
+                    self.ended = true;
+                    return Ok(false);
+                }
+            }
+            if let Some(max_bytes) = self.max_bytes {
+                self.bytes += pair.value().len();
+                if self.bytes > max_bytes {
+                    self.over_budget = true;
+                    return Ok(false);
+                }
+            }
+            result.push(pair);
+        }
+        Ok(true)
+    }
+
+    // Like `assemble`, for the pairs of a response from region `region_id`, which
+    // `with_region_markers` records the start of.
+    fn assemble_region(
+        &mut self,
+        region_id: u64,
+        pairs: Vec<KvPair>,
+        result: &mut Vec<KvPair>,
+    ) -> Result<bool, Error> {
+        if let Some(ref mut region_starts) = self.region_starts {
+            region_starts.push((result.len(), region_id));
+        }
+        self.assemble(pairs, result)
+    }
+
+    /// Interleaves the pairs with a `ScanItem::RegionBoundary` before the first pair of
+    /// each region, so that a long scan can be checkpointed and resumed region by
+    /// region. The regions are the ones that answered the scan, as reported in their
+    /// responses.
+    pub fn with_region_markers(mut self) -> MarkedScan<'a> {
+        self.region_starts = Some(Vec::new());
+        MarkedScan { scan: self }
+    }
+
+    /// Stops the scan before the values gathered exceed `max_bytes` in total, on top
+    /// of the key count `limit`. The future then resolves to the pairs along with
+    /// whether the byte budget cut the scan short.
+    ///
+    /// Like `take_while`, the budget is checked as the pairs arrive: the batch that
+    /// exceeds it has already been read, but no further batches or regions are
+    /// requested.
+    pub fn max_bytes(mut self, max_bytes: usize) -> ByteLimitedScan<'a> {
+        self.max_bytes = Some(max_bytes);
+        ByteLimitedScan { scan: self }
+    }
+
+    /// Resolves to the pairs along with a `ScanToken` for the rest of the range, or
+    /// `None` if the scan reached its end or was ended by `take_while`. Pass the token
+    /// to `Client::scan_resume`, possibly after a restart, to continue where this scan
+    /// stopped.
+    ///
+    /// The token doesn't carry the `take_while` and `map_value` closures, which can't
+    /// be persisted; set them again on the resumed scan.
+    pub fn resumable(self) -> ResumableScan<'a> {
+        ResumableScan { scan: self }
+    }
+}
+
+/// The pairs returned by a `Scan`, in scan order.
+#[derive(Default, Clone, Eq, PartialEq, Debug)]
+pub struct ScanResult(Vec<KvPair>);
+
+impl ScanResult {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn into_pairs(self) -> Vec<KvPair> {
+        self.0
+    }
+}
+
+impl From<Vec<KvPair>> for ScanResult {
+    fn from(pairs: Vec<KvPair>) -> ScanResult {
+        ScanResult(pairs)
+    }
+}
+
+impl Deref for ScanResult {
+    type Target = [KvPair];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl IntoIterator for ScanResult {
+    type Item = (Key, Value);
+    type IntoIter = iter::Map<vec::IntoIter<KvPair>, fn(KvPair) -> (Key, Value)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter().map(KvPair::into_inner)
+    }
+}
+
+impl<'a> Future for Scan<'a> {
+    type Item = ScanResult;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let key_len = self.range.0.len();
+        let _entered = self.instrument.enter("scan", key_len, &self.cf);
+        let result = self.timeout.check().and_then(|()| self.poll_scan());
+        let result = self.timeout.poll(result);
+        self.instrument.done(result)
+    }
+}
+
+impl<'a> Scan<'a> {
+    fn poll_scan(&mut self) -> Poll<ScanResult, Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        let limit = self.client.check_scan_limit(self.limit)?;
+        if Client::is_empty_range(&self.range) {
+            return Ok(Async::Ready(ScanResult::default()));
+        }
+        if let Some(max_concurrency) = self.max_concurrency {
+            if self.parallel.is_none() {
+                let range = self.client.encode_range(&self.range);
+                let mut parallel = Parts::new(&self.client.inner.config);
+                parallel.max_concurrency = max_concurrency;
+                parallel.start(self.client.split_range(&range)?);
+                self.parallel = Some(parallel);
+                self.gathered = Vec::with_capacity(self.result_capacity(limit));
+            }
+            if let Async::NotReady = self.poll_parts()? {
+                return Ok(Async::NotReady);
+            }
+            return Ok(Async::Ready(mem::take(&mut self.gathered).into()));
+        }
+        // The regions are scanned one after the other, in scan order, each with what
+        // is left of the limit.
+        let (start, end) = self.client.encode_range(&self.range);
+        if self.cursor.is_none() {
+            self.cursor = Some(if self.reverse {
+                end.clone()
+            } else {
+                start.clone()
+            });
+            self.gathered = Vec::with_capacity(self.result_capacity(limit));
+        }
+        loop {
+            if let Async::NotReady = self.retry.poll_delay()? {
+                return Ok(Async::NotReady);
+            }
+            let cursor = self.cursor.clone().unwrap();
+            let located = if self.reverse {
+                self.client.locate_before(&cursor)
+            } else {
+                self.client.locate(&cursor)
+            };
+            let region = located?;
+            let range = if self.reverse {
+                (cmp::max(&start, &region.start_key).clone(), cursor.clone())
+            } else if end.is_empty() || (!region.end_key.is_empty() && region.end_key < end) {
+                (cursor.clone(), region.end_key.clone())
+            } else {
+                (cursor.clone(), end.clone())
+            };
+            let remaining = limit - self.received as u32;
+            let pairs = match self.scan_region(&region, &range, remaining) {
+                Ok(Async::Ready(pairs)) => self.client.decode_pairs(pairs)?,
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(err) => {
+                    let region_cache = &self.client.inner.region_cache;
+                    self.retry.on_error(region_cache, &cursor, err, true)?;
+                    continue;
+                }
+            };
+            let mut gathered = mem::take(&mut self.gathered);
+            let more = self.assemble_region(region.id, pairs, &mut gathered)?;
+            self.gathered = gathered;
+            let (next, last) = if self.reverse {
+                let next = region.start_key;
+                let last = next.is_empty() || next <= start;
+                (next, last)
+            } else {
+                let next = region.end_key;
+                let last = next.is_empty() || (!end.is_empty() && next >= end);
+                (next, last)
+            };
+            if !more || last || self.received >= limit as usize {
+                return Ok(Async::Ready(mem::take(&mut self.gathered).into()));
+            }
+            self.cursor = Some(next);
+        }
+    }
+
+    // Resolves to the pairs of the encoded `range` returned by `region`, with encoded
+    // keys.
+    fn scan_region(
+        &self,
+        region: &Region,
+        range: &(Key, Key),
+        limit: u32,
+    ) -> Poll<Vec<KvPair>, Error> {
+        let filter_prefix = self
+            .filter_prefix
+            .as_ref()
+            .map(|prefix| self.client.encode_key(prefix));
+        self.instrument.route(region);
+        #[cfg(feature = "mock")]
+        {
+            if let Some(ref mock) = self.client.inner.mock {
+                if let Async::NotReady = mock.poll_region_request("RawScan", region)? {
+                    return Ok(Async::NotReady);
+                }
+                let mut pairs =
+                    mock.scan(&self.cf, range, limit, self.reverse, filter_prefix.as_ref());
+                if self.key_only {
+                    for pair in &mut pairs {
+                        pair.1 = Value::default();
+                    }
+                }
+                return Ok(Async::Ready(pairs));
+            }
+        }
+        let _ = region;
+        let _ = self.request_bounds(range);
+        let _ = limit;
+        let _ = &self.key_only;
+        let _ = &self.cf;
+        let _ = &self.priority;
+        let _ = &self.deadline;
+        let _ = &self.read_policy;
+        let _ = &self.max_staleness;
+        let _ = filter_prefix;
+        let _ = &self.take_while;
+        let _ = &self.map_value;
+        unimplemented!()
+    }
+}
+
+impl<'a> Fanout for Scan<'a> {
+    type Part = (Key, Key);
+    type Output = (u64, Vec<KvPair>);
+
+    fn parts(&mut self) -> &mut Parts<(Key, Key)> {
+        self.parallel.as_mut().unwrap()
+    }
+
+    fn send(&self, range: &(Key, Key)) -> Poll<(u64, Vec<KvPair>), Error> {
+        let region = self.client.locate(&range.0)?;
+        let limit = self.client.check_scan_limit(self.limit)?;
+        match self.scan_region(&region, range, limit - self.received as u32)? {
+            Async::Ready(pairs) => Ok(Async::Ready((region.id, pairs))),
+            Async::NotReady => Ok(Async::NotReady),
+        }
+    }
+
+    fn done(
+        &mut self,
+        _: (Key, Key),
+        (region_id, pairs): (u64, Vec<KvPair>),
+    ) -> Result<bool, Error> {
+        let limit = self.client.check_scan_limit(self.limit)? as usize;
+        let mut pairs = self.client.decode_pairs(pairs)?;
+        // The regions scanned concurrently were each given the whole limit left.
+        pairs.truncate(limit - self.received);
+        let mut gathered = mem::take(&mut self.gathered);
+        let more = self.assemble_region(region_id, pairs, &mut gathered)?;
+        self.gathered = gathered;
+        Ok(more && self.received < limit)
+    }
+
+    fn failed(&mut self, range: (Key, Key), err: Error) -> Result<Vec<(Key, Key)>, Error> {
+        let region_cache = &self.client.inner.region_cache;
+        self.parts()
+            .retry
+            .on_error(region_cache, &range.0, err, true)?;
+        self.client.split_range(&range)
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum ScanItem {
+    Pair(KvPair),
+    /// The pairs that follow, up to the next boundary, belong to the region with this
+    /// id.
+    RegionBoundary(u64),
+}
+
+pub struct MarkedScan<'a> {
+    scan: Scan<'a>,
+}
+
+impl<'a> Future for MarkedScan<'a> {
+    type Item = Vec<ScanItem>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let pairs = match self.scan.poll()? {
+            Async::Ready(pairs) => pairs.into_pairs(),
+            Async::NotReady => return Ok(Async::NotReady),
+        };
+        let region_starts = self.scan.region_starts.take().unwrap_or_default();
+        Ok(Async::Ready(mark_regions(pairs, &region_starts)))
+    }
+}
+
+// Inserts a boundary before the pairs starting at each of `region_starts`. A region
+// answering several requests in a row, or a response without pairs, adds none.
+fn mark_regions(pairs: Vec<KvPair>, region_starts: &[(usize, u64)]) -> Vec<ScanItem> {
+    let mut items = Vec::with_capacity(pairs.len() + region_starts.len());
+    let mut starts = region_starts.iter().peekable();
+    let mut current = None;
+    for (position, pair) in pairs.into_iter().enumerate() {
+        let mut region_id = None;
+        while let Some(&&(start, id)) = starts.peek() {
+            if start > position {
+                break;
+            }
+            region_id = Some(id);
+            starts.next();
+        }
+        if let Some(id) = region_id {
+            if current != Some(id) {
+                items.push(ScanItem::RegionBoundary(id));
+                current = Some(id);
+            }
+        }
+        items.push(ScanItem::Pair(pair));
+    }
+    items
+}
+
+pub struct ByteLimitedScan<'a> {
+    scan: Scan<'a>,
+}
+
+impl<'a> Future for ByteLimitedScan<'a> {
+    type Item = (Vec<KvPair>, bool);
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let pairs = match self.scan.poll()? {
+            Async::Ready(pairs) => pairs.into_pairs(),
+            Async::NotReady => return Ok(Async::NotReady),
+        };
+        Ok(Async::Ready((pairs, self.scan.over_budget)))
+    }
+}
+
+pub struct ResumableScan<'a> {
+    scan: Scan<'a>,
+}
+
+impl<'a> Future for ResumableScan<'a> {
+    type Item = (ScanResult, Option<ScanToken>);
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let pairs = match self.scan.poll()? {
+            Async::Ready(pairs) => pairs,
+            Async::NotReady => return Ok(Async::NotReady),
+        };
+        // A clamped scan stops at the configured maximum instead of its own limit.
+        let limit = self.scan.client.check_scan_limit(self.scan.limit)?;
+        // The range goes on if TiKV filled the page, however much of it was kept.
+        let full = self.scan.received >= limit as usize;
+        let token = match pairs.last() {
+            Some(last) if full && !self.scan.ended => {
+                Some(ScanToken::after(&self.scan, last.key().clone()))
+            }
+            _ => None,
+        };
+        Ok(Async::Ready((pairs, token)))
+    }
+}
+
+/// The position of a `ResumableScan`, with everything needed to continue it exactly:
+/// the remaining range, its column family, direction and filters.
+///
+/// The token is opaque. It can be persisted with `to_bytes` and restored with
+/// `from_bytes`.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ScanToken {
+    cf: Option<ColumnFamily>,
+    start: Bound<Key>,
+    end: Bound<Key>,
+    reverse: bool,
+    key_only: bool,
+    filter_prefix: Option<Key>,
+}
+
+const SCAN_TOKEN_VERSION: u8 = 1;
+
+const SCAN_TOKEN_REVERSE: u8 = 1;
+const SCAN_TOKEN_KEY_ONLY: u8 = 1 << 1;
+
+const BOUND_INCLUDED: u8 = 0;
+const BOUND_EXCLUDED: u8 = 1;
+const BOUND_UNBOUNDED: u8 = 2;
+
+impl ScanToken {
+    // The rest of `scan`'s range once `last` has been returned.
+    fn after(scan: &Scan, last: Key) -> ScanToken {
+        let (start, end) = if scan.reverse {
+            (Bound::Included(scan.range.0.clone()), Bound::Excluded(last))
+        } else {
+            let end = if scan.range.1.is_empty() {
+                Bound::Unbounded
+            } else {
+                Bound::Excluded(scan.range.1.clone())
+            };
+            (Bound::Excluded(last), end)
+        };
+        ScanToken {
+            cf: scan.cf.clone(),
+            start,
+            end,
+            reverse: scan.reverse,
+            key_only: scan.key_only,
+            filter_prefix: scan.filter_prefix.clone(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = vec![SCAN_TOKEN_VERSION];
+        let mut flags = 0;
+        if self.reverse {
+            flags |= SCAN_TOKEN_REVERSE;
+        }
+        if self.key_only {
+            flags |= SCAN_TOKEN_KEY_ONLY;
+        }
+        buf.push(flags);
+        encode_optional(&mut buf, self.cf.as_ref().map(|cf| cf.as_str().as_bytes()));
+        encode_bound(&mut buf, &self.start);
+        encode_bound(&mut buf, &self.end);
+        encode_optional(
+            &mut buf,
+            self.filter_prefix.as_ref().map(|key| key.as_slice()),
+        );
+        buf
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<ScanToken, Error> {
+        if data.len() < 2 || data[0] != SCAN_TOKEN_VERSION {
+            return Err(Error::Codec("unsupported scan token".to_owned()));
+        }
+        let flags = data[1];
+        let (cf, rest) = decode_optional(&data[2..])?;
+        let cf = match cf {
+            Some(cf) => Some(ColumnFamily::from(String::from_utf8(cf).map_err(|_| {
+                Error::Codec("invalid column family in scan token".to_owned())
+            })?)),
+            None => None,
+        };
+        let (start, rest) = decode_bound(rest)?;
+        let (end, rest) = decode_bound(rest)?;
+        let (filter_prefix, rest) = decode_optional(rest)?;
+        if !rest.is_empty() {
+            return Err(Error::Codec("trailing bytes in scan token".to_owned()));
+        }
+        Ok(ScanToken {
+            cf,
+            start,
+            end,
+            reverse: flags & SCAN_TOKEN_REVERSE != 0,
+            key_only: flags & SCAN_TOKEN_KEY_ONLY != 0,
+            filter_prefix: filter_prefix.map(Into::into),
+        })
+    }
+}
+
+fn encode_optional(buf: &mut Vec<u8>, data: Option<&[u8]>) {
+    match data {
+        Some(data) => {
+            buf.push(1);
+            codec::encode_bytes(buf, data);
+        }
+        None => buf.push(0),
+    }
+}
+
+fn decode_optional(data: &[u8]) -> Result<(Option<Vec<u8>>, &[u8]), Error> {
+    match data.split_first() {
+        Some((&0, rest)) => Ok((None, rest)),
+        Some((&1, rest)) => codec::decode_bytes(rest).map(|(bytes, rest)| (Some(bytes), rest)),
+        _ => Err(Error::Codec("truncated scan token".to_owned())),
+    }
+}
+
+fn encode_bound(buf: &mut Vec<u8>, bound: &Bound<Key>) {
+    match *bound {
+        Bound::Included(ref key) => {
+            buf.push(BOUND_INCLUDED);
+            codec::encode_bytes(buf, key);
+        }
+        Bound::Excluded(ref key) => {
+            buf.push(BOUND_EXCLUDED);
+            codec::encode_bytes(buf, key);
+        }
+        Bound::Unbounded => buf.push(BOUND_UNBOUNDED),
+    }
+}
+
+fn decode_bound(data: &[u8]) -> Result<(Bound<Key>, &[u8]), Error> {
+    let (tag, rest) = match data.split_first() {
+        Some((tag, rest)) => (*tag, rest),
+        None => return Err(Error::Codec("truncated scan token".to_owned())),
+    };
+    if tag == BOUND_UNBOUNDED {
+        return Ok((Bound::Unbounded, rest));
+    }
+    let (key, rest) = codec::decode_bytes(rest)?;
+    match tag {
+        BOUND_INCLUDED => Ok((Bound::Included(key.into()), rest)),
+        BOUND_EXCLUDED => Ok((Bound::Excluded(key.into()), rest)),
+        _ => Err(Error::Codec(format!("invalid bound {} in scan token", tag))),
+    }
+}
+
+pub struct Paginator<'a> {
+    client: &'a Client,
+    // `None` once the range is exhausted.
+    start: Option<Key>,
+    end: Key,
+    page_size: u32,
+}
+
+impl<'a> Paginator<'a> {
+    fn new(client: &'a Client, range: (Key, Key), page_size: u32) -> Self {
+        Paginator {
+            client,
+            start: Some(range.0),
+            end: range.1,
+            page_size,
+        }
+    }
+
+    /// Fetches the next page, resolving to an empty `Vec` once the range is exhausted.
+    pub fn next_page(&mut self) -> NextPage<'a, '_> {
+        let scan = self.start.as_ref().map(|start| {
+            Scan::new(
+                self.client,
+                (start.clone(), self.end.clone()),
+                self.page_size,
+            )
+        });
+        NextPage {
+            scan,
+            start: &mut self.start,
+            page_size: self.page_size,
+        }
+    }
+}
+
+pub struct NextPage<'a, 'b> {
+    scan: Option<Scan<'a>>,
+    start: &'b mut Option<Key>,
+    page_size: u32,
+}
+
+impl<'a, 'b> Future for NextPage<'a, 'b> {
+    type Item = Vec<KvPair>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let (pairs, page_size) = match self.scan {
+            Some(ref mut scan) => match scan.poll()? {
+                Async::Ready(pairs) => (
+                    pairs.into_pairs(),
+                    scan.client.check_scan_limit(self.page_size)?,
+                ),
+                Async::NotReady => return Ok(Async::NotReady),
+            },
+            None => return Ok(Async::Ready(Vec::new())),
+        };
+        *self.start = match pairs.last() {
+            Some(last) if pairs.len() as u32 >= page_size => Some(last.key().next()),
+            _ => None,
+        };
+        Ok(Async::Ready(pairs))
+    }
+}
+
+pub struct ScanKeys<'a> {
+    client: &'a Client,
+    range: (Key, Key),
+    limit: u32,
+    cf: Option<ColumnFamily>,
+    priority: Priority,
+    reverse: bool,
+    in_flight: Option<InFlight<'a>>,
+}
+
+impl<'a> ScanKeys<'a> {
+    fn new(client: &'a Client, range: (Key, Key), limit: u32) -> Self {
+        ScanKeys {
+            client,
+            range,
+            limit,
+            cf: client.cf.clone(),
+            priority: client.inner.config.default_priority,
+            reverse: false,
+            in_flight: None,
+        }
+    }
+
+    pub fn cf(mut self, cf: impl Into<ColumnFamily>) -> Self {
+        self.cf = Some(cf.into());
+        self
+    }
+
+    /// See `Get::priority`.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn reverse(mut self) -> Self {
+        self.reverse = true;
+        self
+    }
+}
+
+impl<'a> Future for ScanKeys<'a> {
+    type Item = Vec<Key>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        let limit = self.client.check_scan_limit(self.limit)?;
+        if Client::is_empty_range(&self.range) {
+            return Ok(Async::Ready(Vec::new()));
+        }
+        #[cfg(feature = "mock")]
+        {
+            if let Some(ref mock) = self.client.inner.mock {
+                let range = self.client.encode_range(&self.range);
+                let pairs = mock.scan(&self.cf, &range, limit, self.reverse, None);
+                return Ok(Async::Ready(
+                    self.client
+                        .decode_pairs(pairs)?
+                        .into_iter()
+                        .map(|pair| pair.into_inner().0)
+                        .collect(),
+                ));
+            }
+        }
+        let _ = self.client.encode_range(&self.range);
+        let _ = limit;
+        let _ = &self.cf;
+        let _ = &self.priority;
+        let _ = &self.reverse;
+        unimplemented!()
+    }
+}
+
+const DEFAULT_SCAN_BATCH_SIZE: u32 = 256;
+
+pub struct ScanStream<'a> {
+    scan: Scan<'a>,
+    batch_size: u32,
+    // The pairs of the last batch not yet yielded.
+    batch: vec::IntoIter<KvPair>,
+    // The number of pairs fetched so far, counted against the scan's `limit`.
+    fetched: u32,
+    done: bool,
+}
+
+impl<'a> ScanStream<'a> {
+    fn new(scan: Scan<'a>) -> Self {
+        ScanStream {
+            scan,
+            batch_size: DEFAULT_SCAN_BATCH_SIZE,
+            batch: Vec::new().into_iter(),
+            fetched: 0,
+            done: false,
+        }
+    }
+
+    pub fn batch_size(mut self, batch_size: u32) -> Self {
+        self.batch_size = cmp::max(batch_size, 1);
+        self
+    }
+
+    // Fetches the next batch, narrowing the scan's range to the part left after it.
+    fn fetch(&mut self) -> Poll<Vec<KvPair>, Error> {
+        let limit = cmp::min(self.batch_size, self.scan.limit - self.fetched);
+        #[cfg(feature = "mock")]
+        {
+            if let Some(ref mock) = self.scan.client.inner.mock {
+                let scan = &mut self.scan;
+                let client = scan.client;
+                let range = client.encode_range(&scan.range);
+                let filter_prefix = scan.filter_prefix.as_ref().map(|p| client.encode_key(p));
+                let pairs = mock.scan(
+                    &scan.cf,
+                    &range,
+                    limit,
+                    scan.reverse,
+                    filter_prefix.as_ref(),
+                );
+                let mut pairs = client.decode_pairs(pairs)?;
+                self.fetched += pairs.len() as u32;
+                self.done = (pairs.len() as u32) < limit || self.fetched == scan.limit;
+                if let Some(last) = pairs.last() {
+                    if scan.reverse {
+                        // An empty end would read as unbounded; nothing is below "".
+                        self.done |= last.key().is_empty();
+                        scan.range.1 = last.key().clone();
+                    } else {
+                        scan.range.0 = last.key().next();
+                    }
+                }
+                if scan.key_only {
+                    for pair in &mut pairs {
+                        pair.1 = Value::default();
+                    }
+                }
+                let mut batch = Vec::with_capacity(pairs.len());
+                if !scan.assemble(pairs, &mut batch)? {
+                    self.done = true;
+                }
+                return Ok(Async::Ready(batch));
+            }
+        }
+        let _ = limit;
+        let _ = &self.scan;
+        unimplemented!()
+    }
+}
+
+impl<'a> Stream for ScanStream<'a> {
+    type Item = KvPair;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.scan.client.check_open(&mut self.scan.in_flight)?;
+        loop {
+            if let Some(pair) = self.batch.next() {
+                return Ok(Async::Ready(Some(pair)));
+            }
+            if self.done
+                || self.fetched == self.scan.limit
+                || Client::is_empty_range(&self.scan.range)
+            {
+                return Ok(Async::Ready(None));
+            }
+            self.batch = match self.fetch()? {
+                Async::Ready(batch) => batch.into_iter(),
+                Async::NotReady => return Ok(Async::NotReady),
+            };
+        }
+    }
+}
+
+pub struct BatchScan<'a> {
+    client: &'a Client,
+    ranges: Vec<(Key, Key)>,
+    each_limit: u32,
+    key_only: bool,
+    cf: Option<ColumnFamily>,
+    priority: Priority,
+    reverse: bool,
+    dedup: bool,
+    // One part per region of each range, tagged with the index of the range.
+    parts: Parts<(usize, (Key, Key))>,
+    // The pairs returned for each range, by the start of the part they were
+    // returned for.
+    scanned: Vec<BTreeMap<Key, Vec<KvPair>>>,
+    instrument: Instrument,
+    in_flight: Option<InFlight<'a>>,
+}
+
+impl<'a> BatchScan<'a> {
+    fn new(client: &'a Client, ranges: Vec<(Key, Key)>, each_limit: u32) -> Self {
+        BatchScan {
+            client,
+            ranges,
+            each_limit,
+            key_only: false,
+            cf: client.cf.clone(),
+            priority: client.inner.config.default_priority,
+            reverse: false,
+            dedup: false,
+            parts: Parts::new(&client.inner.config),
+            scanned: Vec::new(),
+            instrument: Instrument::default(),
+            in_flight: None,
+        }
+    }
+
+    pub fn key_only(mut self) -> Self {
+        self.key_only = true;
+        self
+    }
+
+    pub fn cf(mut self, cf: impl Into<ColumnFamily>) -> Self {
+        self.cf = Some(cf.into());
+        self
+    }
+
+    /// See `Get::priority`.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Scans each range from its upper bound down to its lower bound, so the pairs of
+    /// every range come back in descending key order and `each_limit` keeps the
+    /// highest keys of each range.
+    pub fn reverse(mut self) -> Self {
+        self.reverse = true;
+        self
+    }
+
+    /// Drops pairs whose key was already returned by an earlier range, keeping the
+    /// first occurrence. This holds every returned key in memory until the scan
+    /// completes, so it's off by default.
+    pub fn dedup(mut self) -> Self {
+        self.dedup = true;
+        self
+    }
+
+    /// Caps the number of region sub-scans in flight at the same time. A range that
+    /// spans several regions is scanned in all of them concurrently, and the results
+    /// are merged back in key order, stopping at `each_limit`.
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.parts.max_concurrency = cmp::max(max_concurrency, 1);
+        self
+    }
+
+    /// Turns the batch scan into a stream that scans the ranges one after another as
+    /// the consumer polls it. Each pair comes with the index of the range it belongs
+    /// to, and `each_limit` still applies per range.
+    pub fn into_stream(self) -> BatchScanStream<'a> {
+        BatchScanStream {
+            batch_scan: self,
+            next_range: 0,
+            scan: None,
+            pairs: Vec::new().into_iter(),
+            seen: HashSet::new(),
+        }
+    }
+
+    fn scan_range(&self, index: usize) -> Scan<'a> {
+        let mut scan = Scan::new(self.client, self.ranges[index].clone(), self.each_limit);
+        scan.key_only = self.key_only;
+        scan.cf = self.cf.clone();
+        scan.priority = self.priority;
+        scan.reverse = self.reverse;
+        scan
+    }
+}
+
+impl<'a> Future for BatchScan<'a> {
+    type Item = Vec<KvPair>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let key_len = self.ranges.iter().map(|range| range.0.len()).sum();
+        let _entered = self.instrument.enter("batch_scan", key_len, &self.cf);
+        let result = self.poll_scan();
+        self.instrument.done(result)
+    }
+}
+
+impl<'a> BatchScan<'a> {
+    fn poll_scan(&mut self) -> Poll<Vec<KvPair>, Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        let each_limit = self.client.check_scan_limit(self.each_limit)?;
+        if !self.parts.started {
+            let mut parts = Vec::new();
+            for (index, range) in self.ranges.iter().enumerate() {
+                if Client::is_empty_range(range) {
+                    continue;
+                }
+                let range = self.client.encode_range(range);
+                for part in self.client.split_range(&range)? {
+                    parts.push((index, part));
+                }
+            }
+            self.scanned = vec![BTreeMap::new(); self.ranges.len()];
+            self.parts.start(parts);
+        }
+        if let Async::NotReady = self.poll_parts()? {
+            return Ok(Async::NotReady);
+        }
+        // Each part was scanned with the whole `each_limit`: the pairs of a range are
+        // merged in scan order and cut at the limit.
+        let mut seen = HashSet::new();
+        let mut pairs = Vec::new();
+        for scanned in mem::take(&mut self.scanned) {
+            let mut parts: Vec<Vec<KvPair>> = scanned.into_values().collect();
+            if self.reverse {
+                parts.reverse();
+            }
+            let range_pairs = parts.into_iter().flatten().take(each_limit as usize);
+            for mut pair in self.client.decode_pairs(range_pairs.collect())? {
+                if self.dedup && !seen.insert(pair.key().clone()) {
+                    continue;
+                }
+                if self.key_only {
+                    pair.1 = Value::default();
+                }
+                pairs.push(pair);
+            }
+        }
+        Ok(Async::Ready(pairs))
+    }
+}
+
+impl<'a> Fanout for BatchScan<'a> {
+    type Part = (usize, (Key, Key));
+    type Output = Vec<KvPair>;
+
+    fn parts(&mut self) -> &mut Parts<(usize, (Key, Key))> {
+        &mut self.parts
+    }
+
+    fn send(&self, (_, range): &(usize, (Key, Key))) -> Poll<Vec<KvPair>, Error> {
+        let region = self.client.locate(&range.0)?;
+        self.instrument.route(&region);
+        let each_limit = self.client.check_scan_limit(self.each_limit)?;
+        #[cfg(feature = "mock")]
+        {
+            if let Some(ref mock) = self.client.inner.mock {
+                if let Async::NotReady = mock.poll_region_request("RawScan", &region)? {
+                    return Ok(Async::NotReady);
+                }
+                let pairs = mock.scan(&self.cf, range, each_limit, self.reverse, None);
+                return Ok(Async::Ready(pairs));
+            }
+        }
+        let _ = region;
+        let _ = each_limit;
+        let _ = &self.key_only;
+        let _ = &self.cf;
+        let _ = &self.priority;
+        let _ = &self.reverse;
+        unimplemented!()
+    }
+
+    fn done(
+        &mut self,
+        (index, range): (usize, (Key, Key)),
+        pairs: Vec<KvPair>,
+    ) -> Result<bool, Error> {
+        self.scanned[index].insert(range.0, pairs);
+        Ok(true)
+    }
+
+    fn failed(
+        &mut self,
+        (index, range): (usize, (Key, Key)),
+        err: Error,
+    ) -> Result<Vec<(usize, (Key, Key))>, Error> {
+        let region_cache = &self.client.inner.region_cache;
+        self.parts
+            .retry
+            .on_error(region_cache, &range.0, err, true)?;
+        let parts = self.client.split_range(&range)?;
+        Ok(parts.into_iter().map(|part| (index, part)).collect())
+    }
+}
+
+pub struct BatchScanStream<'a> {
+    batch_scan: BatchScan<'a>,
+    next_range: usize,
+    scan: Option<Scan<'a>>,
+    // The pairs of range `next_range - 1` that haven't been yielded yet.
+    pairs: vec::IntoIter<KvPair>,
+    seen: HashSet<Key>,
+}
+
+impl<'a> Stream for BatchScanStream<'a> {
+    type Item = (usize, KvPair);
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if let Some(pair) = self.pairs.next() {
+                if self.batch_scan.dedup && !self.seen.insert(pair.key().clone()) {
+                    continue;
+                }
+                return Ok(Async::Ready(Some((self.next_range - 1, pair))));
+            }
+            if self.scan.is_none() {
+                if self.next_range == self.batch_scan.ranges.len() {
+                    return Ok(Async::Ready(None));
+                }
+                self.scan = Some(self.batch_scan.scan_range(self.next_range));
+                self.next_range += 1;
+            }
+            let pairs = match self.scan.as_mut().unwrap().poll()? {
+                Async::Ready(pairs) => pairs,
+                Async::NotReady => return Ok(Async::NotReady),
+            };
+            self.pairs = pairs.into_pairs().into_iter();
+            self.scan = None;
+        }
+    }
+}
+
+pub struct DeleteRange<'a> {
+    client: &'a Client,
+    range: (Key, Key),
+    cf: Option<ColumnFamily>,
+    limit: u32,
+    dry_run: bool,
+    on_progress: Option<Box<FnMut(usize, usize) + Send + 'a>>,
+    // One part per region of the encoded range.
+    parts: Parts<(Key, Key)>,
+    deleted: u32,
+    // The regions handled so far, out of the regions of the range.
+    regions_done: usize,
+    regions: usize,
+    instrument: Instrument,
+    in_flight: Option<InFlight<'a>>,
+}
+
+impl<'a> DeleteRange<'a> {
+    fn new(client: &'a Client, range: (Key, Key)) -> Self {
+        DeleteRange {
+            client,
+            range,
+            cf: client.cf.clone(),
+            limit: u32::MAX,
+            dry_run: false,
+            on_progress: None,
+            parts: Parts::new(&client.inner.config),
+            deleted: 0,
+            regions_done: 0,
+            regions: 0,
+            instrument: Instrument::default(),
+            in_flight: None,
+        }
+    }
+
+    pub fn cf(mut self, cf: impl Into<ColumnFamily>) -> Self {
+        self.cf = Some(cf.into());
+        self
+    }
+
+    /// Stops after deleting `limit` keys. Defaults to `u32::MAX`. With a limit, the
+    /// regions are deleted from one at a time, so that the limit isn't overshot.
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Counts the keys that would be deleted without deleting anything.
+    pub fn dry_run(mut self) -> Self {
+        self.dry_run = true;
+        self
+    }
+
+    /// Caps the number of regions being deleted from at the same time.
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.parts.max_concurrency = cmp::max(max_concurrency, 1);
+        self
+    }
+
+    /// Calls `f(done, total)` each time a region of the range has been handled, where
+    /// `total` is the number of regions the range overlaps once they are resolved.
+    pub fn on_progress(mut self, f: impl FnMut(usize, usize) + Send + 'a) -> Self {
+        self.on_progress = Some(Box::new(f));
+        self
+    }
+}
+
+impl<'a> Future for DeleteRange<'a> {
+    type Item = u32;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let key_len = self.range.0.len();
+        let _entered = self.instrument.enter("delete_range", key_len, &self.cf);
+        let result = self.poll_delete();
+        self.instrument.done(result)
+    }
+}
+
+impl<'a> DeleteRange<'a> {
+    fn poll_delete(&mut self) -> Poll<u32, Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        if !self.parts.started {
+            if self.limit == 0 || Client::is_empty_range(&self.range) {
+                return Ok(Async::Ready(0));
+            }
+            let range = self.client.encode_range(&self.range);
+            let parts = self.client.split_range(&range)?;
+            self.regions = parts.len();
+            if self.limit < u32::MAX {
+                self.parts.max_concurrency = 1;
+            }
+            self.parts.start(parts);
+        }
+        if let Async::NotReady = self.poll_parts()? {
+            return Ok(Async::NotReady);
+        }
+        Ok(Async::Ready(self.deleted))
+    }
+}
+
+impl<'a> Fanout for DeleteRange<'a> {
+    type Part = (Key, Key);
+    type Output = u32;
+
+    fn parts(&mut self) -> &mut Parts<(Key, Key)> {
+        &mut self.parts
+    }
+
+    fn send(&self, range: &(Key, Key)) -> Poll<u32, Error> {
+        let region = self.client.locate(&range.0)?;
+        self.instrument.route(&region);
+        let limit = self.limit - self.deleted;
+        #[cfg(feature = "mock")]
+        {
+            if let Some(ref mock) = self.client.inner.mock {
+                if let Async::NotReady = mock.poll_region_request("RawDeleteRange", &region)? {
+                    return Ok(Async::NotReady);
+                }
+                let deleted = mock.delete_range(&self.cf, range, limit, self.dry_run);
+                return Ok(Async::Ready(deleted));
+            }
+        }
+        let _ = region;
+        let _ = limit;
+        let _ = &self.cf;
+        let _ = &self.dry_run;
+        unimplemented!()
+    }
+
+    fn done(&mut self, _: (Key, Key), deleted: u32) -> Result<bool, Error> {
+        self.deleted += deleted;
+        self.regions_done += 1;
+        if let Some(ref mut on_progress) = self.on_progress {
+            on_progress(self.regions_done, self.regions);
+        }
+        Ok(self.deleted < self.limit)
+    }
+
+    fn failed(&mut self, range: (Key, Key), err: Error) -> Result<Vec<(Key, Key)>, Error> {
+        let region_cache = &self.client.inner.region_cache;
+        self.parts
+            .retry
+            .on_error(region_cache, &range.0, err, true)?;
+        // The range of a region that was split is now handled in several parts.
+        let parts = self.client.split_range(&range)?;
+        self.regions += parts.len() - 1;
+        Ok(parts)
+    }
+}
+
+pub struct UnsafeDestroyRange<'a> {
+    client: &'a Client,
+    range: (Key, Key),
+    // One part per store, by a peer on it.
+    parts: Parts<Peer>,
+    instrument: Instrument,
+    in_flight: Option<InFlight<'a>>,
+}
+
+impl<'a> UnsafeDestroyRange<'a> {
+    fn new(client: &'a Client, range: (Key, Key)) -> Self {
+        UnsafeDestroyRange {
+            client,
+            range,
+            parts: Parts::new(&client.inner.config),
+            instrument: Instrument::default(),
+            in_flight: None,
+        }
+    }
+}
+
+impl<'a> Future for UnsafeDestroyRange<'a> {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let key_len = self.range.0.len();
+        let _entered = self
+            .instrument
+            .enter("unsafe_destroy_range", key_len, &None);
+        let result = self.poll_destroy();
+        self.instrument.done(result)
+    }
+}
+
+impl<'a> UnsafeDestroyRange<'a> {
+    fn poll_destroy(&mut self) -> Poll<(), Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        if !self.parts.started {
+            if Client::is_empty_range(&self.range) {
+                return Ok(Async::Ready(()));
+            }
+            let stores = self
+                .client
+                .stores_in(&self.client.encode_range(&self.range))?;
+            self.parts.start(stores);
+        }
+        self.poll_parts()
+    }
+}
+
+impl<'a> Fanout for UnsafeDestroyRange<'a> {
+    type Part = Peer;
+    type Output = ();
+
+    fn parts(&mut self) -> &mut Parts<Peer> {
+        &mut self.parts
+    }
+
+    fn send(&self, peer: &Peer) -> Poll<(), Error> {
+        self.instrument.route_store(peer);
+        let range = self.client.encode_range(&self.range);
+        #[cfg(feature = "mock")]
+        {
+            if let Some(ref mock) = self.client.inner.mock {
+                if let Async::NotReady = mock.poll_request("UnsafeDestroyRange", peer.store_id)? {
+                    return Ok(Async::NotReady);
+                }
+                mock.destroy_range(&range);
+                return Ok(Async::Ready(()));
+            }
+        }
+        let _ = range;
+        unimplemented!()
+    }
+
+    fn done(&mut self, _: Peer, _: ()) -> Result<bool, Error> {
+        Ok(true)
+    }
+
+    fn failed(&mut self, peer: Peer, err: Error) -> Result<Vec<Peer>, Error> {
+        let key = self.client.encode_key(&self.range.0);
+        let region_cache = &self.client.inner.region_cache;
+        self.parts.retry.on_error(region_cache, &key, err, true)?;
+        Ok(vec![peer])
+    }
+}
+
+pub struct CompactRange<'a> {
+    client: &'a Client,
+    range: (Key, Key),
+    cf: Option<ColumnFamily>,
+    threads: Option<u32>,
+    // One part per store, by a peer on it.
+    parts: Parts<Peer>,
+    instrument: Instrument,
+    in_flight: Option<InFlight<'a>>,
+}
+
+impl<'a> CompactRange<'a> {
+    fn new(client: &'a Client, range: (Key, Key)) -> Self {
+        CompactRange {
+            client,
+            range,
+            cf: client.cf.clone(),
+            threads: None,
+            parts: Parts::new(&client.inner.config),
+            instrument: Instrument::default(),
+            in_flight: None,
+        }
+    }
+
+    pub fn cf(mut self, cf: impl Into<ColumnFamily>) -> Self {
+        self.cf = Some(cf.into());
+        self
+    }
+
+    pub fn threads(mut self, threads: u32) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+}
+
+impl<'a> Future for CompactRange<'a> {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let key_len = self.range.0.len();
+        let _entered = self.instrument.enter("compact_range", key_len, &self.cf);
+        let result = self.poll_compact();
+        self.instrument.done(result)
+    }
+}
+
+impl<'a> CompactRange<'a> {
+    fn poll_compact(&mut self) -> Poll<(), Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        if !self.parts.started {
+            if Client::is_empty_range(&self.range) {
+                return Ok(Async::Ready(()));
+            }
+            let stores = self
+                .client
+                .stores_in(&self.client.encode_range(&self.range))?;
+            self.parts.start(stores);
+        }
+        self.poll_parts()
+    }
+}
+
+impl<'a> Fanout for CompactRange<'a> {
+    type Part = Peer;
+    type Output = ();
+
+    fn parts(&mut self) -> &mut Parts<Peer> {
+        &mut self.parts
+    }
+
+    fn send(&self, peer: &Peer) -> Poll<(), Error> {
+        self.instrument.route_store(peer);
+        let range = self.client.encode_range(&self.range);
+        #[cfg(feature = "mock")]
+        {
+            if let Some(ref mock) = self.client.inner.mock {
+                // The mock store has nothing to compact.
+                return mock.poll_request("CompactRange", peer.store_id);
+            }
+        }
+        let _ = range;
+        let _ = &self.cf;
+        let _ = &self.threads;
+        unimplemented!()
+    }
+
+    fn done(&mut self, _: Peer, _: ()) -> Result<bool, Error> {
+        Ok(true)
+    }
+
+    fn failed(&mut self, peer: Peer, err: Error) -> Result<Vec<Peer>, Error> {
+        let key = self.client.encode_key(&self.range.0);
+        let region_cache = &self.client.inner.region_cache;
+        self.parts.retry.on_error(region_cache, &key, err, true)?;
+        Ok(vec![peer])
+    }
+}
+
+#[derive(Default, Copy, Clone, Eq, PartialEq, Debug)]
+pub struct ChecksumResult {
+    pub checksum: u64,
+    pub total_kvs: u64,
+    pub total_bytes: u64,
+}
+
+impl ChecksumResult {
+    /// Combines the checksums of two disjoint ranges: the crc64 values are XORed and
+    /// the counts summed, as TiDB's `ADMIN CHECKSUM` does.
+    pub fn merge(self, other: ChecksumResult) -> ChecksumResult {
+        ChecksumResult {
+            checksum: self.checksum ^ other.checksum,
+            total_kvs: self.total_kvs + other.total_kvs,
+            total_bytes: self.total_bytes + other.total_bytes,
+        }
+    }
+}
+
+pub struct Checksum<'a> {
+    client: &'a Client,
+    range: (Key, Key),
+    // One part per region of the encoded range.
+    parts: Parts<(Key, Key)>,
+    result: ChecksumResult,
+    instrument: Instrument,
+    in_flight: Option<InFlight<'a>>,
+}
+
+impl<'a> Checksum<'a> {
+    fn new(client: &'a Client, range: (Key, Key)) -> Self {
+        Checksum {
+            client,
+            range,
+            parts: Parts::new(&client.inner.config),
+            result: ChecksumResult::default(),
+            instrument: Instrument::default(),
+            in_flight: None,
+        }
+    }
+}
+
+impl<'a> Future for Checksum<'a> {
+    type Item = ChecksumResult;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let key_len = self.range.0.len();
+        let _entered = self.instrument.enter("checksum", key_len, &None);
+        let result = self.poll_checksum();
+        self.instrument.done(result)
+    }
+}
+
+impl<'a> Checksum<'a> {
+    fn poll_checksum(&mut self) -> Poll<ChecksumResult, Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        if !self.parts.started {
+            if Client::is_empty_range(&self.range) {
+                return Ok(Async::Ready(ChecksumResult::default()));
+            }
+            let range = self.client.encode_range(&self.range);
+            self.parts.start(self.client.split_range(&range)?);
+        }
+        if let Async::NotReady = self.poll_parts()? {
+            return Ok(Async::NotReady);
+        }
+        Ok(Async::Ready(self.result))
+    }
+}
+
+impl<'a> Fanout for Checksum<'a> {
+    type Part = (Key, Key);
+    type Output = ChecksumResult;
+
+    fn parts(&mut self) -> &mut Parts<(Key, Key)> {
+        &mut self.parts
+    }
+
+    fn send(&self, range: &(Key, Key)) -> Poll<ChecksumResult, Error> {
+        let region = self.client.locate(&range.0)?;
+        self.instrument.route(&region);
+        #[cfg(feature = "mock")]
+        {
+            if let Some(ref mock) = self.client.inner.mock {
+                if let Async::NotReady = mock.poll_region_request("RawChecksum", &region)? {
+                    return Ok(Async::NotReady);
+                }
+                return Ok(Async::Ready(mock.checksum(range)));
+            }
+        }
+        let _ = region;
+        unimplemented!()
+    }
+
+    fn done(&mut self, _: (Key, Key), checksum: ChecksumResult) -> Result<bool, Error> {
+        self.result = self.result.merge(checksum);
+        Ok(true)
+    }
+
+    fn failed(&mut self, range: (Key, Key), err: Error) -> Result<Vec<(Key, Key)>, Error> {
+        let region_cache = &self.client.inner.region_cache;
+        self.parts
+            .retry
+            .on_error(region_cache, &range.0, err, true)?;
+        self.client.split_range(&range)
+    }
+}
+
+pub struct CompareAndSwap<'a> {
+    client: &'a Client,
+    key: Key,
+    previous_value: Option<Value>,
+    new_value: Value,
+    cf: Option<ColumnFamily>,
+    idempotent: bool,
+    instrument: Instrument,
+    in_flight: Option<InFlight<'a>>,
+}
+
+impl<'a> CompareAndSwap<'a> {
+    fn new(client: &'a Client, key: Key, previous_value: Option<Value>, new_value: Value) -> Self {
+        CompareAndSwap {
+            client,
+            key,
+            previous_value,
+            new_value,
+            cf: client.cf.clone(),
+            idempotent: false,
+            instrument: Instrument::default(),
+            in_flight: None,
+        }
+    }
+
+    pub fn cf(mut self, cf: impl Into<ColumnFamily>) -> Self {
+        self.cf = Some(cf.into());
+        self
+    }
+
+    /// See `Put::idempotent`. Defaults to `false`, as a retried swap may report a
+    /// failure caused by its own earlier attempt.
+    pub fn idempotent(mut self, idempotent: bool) -> Self {
+        self.idempotent = idempotent;
+        self
+    }
+}
+
+impl<'a> Future for CompareAndSwap<'a> {
+    type Item = (Value, bool);
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let key_len = self.key.len();
+        let _entered = self.instrument.enter("compare_and_swap", key_len, &self.cf);
+        let result = self.poll_swap();
+        self.instrument.done(result)
+    }
+}
+
+impl<'a> CompareAndSwap<'a> {
+    fn poll_swap(&mut self) -> Poll<(Value, bool), Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        let key = self.client.encode_key(&self.key);
+        let region = self.client.locate(&key)?;
+        self.instrument.route(&region);
+        #[cfg(feature = "mock")]
+        {
+            if let Some(ref mock) = self.client.inner.mock {
+                return Ok(Async::Ready(mock.compare_and_swap(
+                    &self.cf,
+                    &key,
+                    &self.previous_value,
+                    &self.new_value,
+                )));
+            }
+        }
+        let _ = region;
+        let _ = key;
+        let _ = &self.previous_value;
+        let _ = &self.new_value;
+        let _ = &self.cf;
+        let _ = &self.idempotent;
+        unimplemented!()
+    }
+}
+
+pub struct PutIfAbsent<'a> {
+    cas: CompareAndSwap<'a>,
+}
+
+impl<'a> PutIfAbsent<'a> {
+    fn new(client: &'a Client, key: Key, value: Value) -> Self {
+        PutIfAbsent {
+            cas: CompareAndSwap::new(client, key, None, value),
+        }
+    }
+
+    pub fn cf(mut self, cf: impl Into<ColumnFamily>) -> Self {
+        self.cas = self.cas.cf(cf);
+        self
+    }
+}
+
+impl<'a> Future for PutIfAbsent<'a> {
+    type Item = bool;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.cas.poll()? {
+            Async::Ready((_, swapped)) => Ok(Async::Ready(swapped)),
+            Async::NotReady => Ok(Async::NotReady),
+        }
+    }
+}
+
+/// A pre-built RocksDB SST file and the key range it covers, used to route it to
+/// the right regions.
+#[derive(Clone, Debug)]
+pub struct SstFile {
+    path: PathBuf,
+    range: (Key, Key),
+}
+
+impl SstFile {
+    pub fn new(path: impl Into<PathBuf>, range: impl KeyRange) -> Self {
+        SstFile {
+            path: path.into(),
+            range: Client::extract_range(&range.into_bounds()),
+        }
+    }
+}
+
+pub struct Ingest<'a> {
+    client: &'a Client,
+    sst: SstFile,
+    instrument: Instrument,
+    in_flight: Option<InFlight<'a>>,
+}
+
+impl<'a> Ingest<'a> {
+    fn new(client: &'a Client, sst: SstFile) -> Self {
+        Ingest {
+            client,
+            sst,
+            instrument: Instrument::default(),
+            in_flight: None,
+        }
+    }
+}
+
+impl<'a> Future for Ingest<'a> {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let key_len = self.sst.range.0.len();
+        let _entered = self.instrument.enter("ingest", key_len, &None);
+        let result = self.poll_ingest();
+        self.instrument.done(result)
+    }
+}
+
+impl<'a> Ingest<'a> {
+    fn poll_ingest(&mut self) -> Poll<(), Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        self.client.check_mock_supported("Ingest")?;
+        let _ = &self.sst.path;
+        let _ = self.client.encode_range(&self.sst.range);
+        unimplemented!()
+    }
+}
+
+pub struct GetRegion<'a> {
+    client: &'a Client,
+    key: Key,
+    in_flight: Option<InFlight<'a>>,
+}
+
+impl<'a> GetRegion<'a> {
+    fn new(client: &'a Client, key: Key) -> Self {
+        GetRegion {
+            client,
+            key,
+            in_flight: None,
+        }
+    }
+}
+
+impl<'a> Future for GetRegion<'a> {
+    type Item = Region;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        let key = self.client.encode_key(&self.key);
+        self.client.locate(&key).map(Async::Ready)
+    }
+}
+
+pub struct RegionsInRange<'a> {
+    client: &'a Client,
+    range: (Key, Key),
+    in_flight: Option<InFlight<'a>>,
+}
+
+impl<'a> RegionsInRange<'a> {
+    fn new(client: &'a Client, range: (Key, Key)) -> Self {
+        RegionsInRange {
+            client,
+            range,
+            in_flight: None,
+        }
+    }
+}
+
+impl<'a> Future for RegionsInRange<'a> {
+    type Item = Vec<Region>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        let range = self.client.encode_range(&self.range);
+        #[cfg(feature = "mock")]
+        {
+            if let Some(ref mock) = self.client.inner.mock {
+                return Ok(Async::Ready(mock.regions_in(&range)));
+            }
+        }
+        let _ = range;
+        unimplemented!()
+    }
+}
+
+pub struct Preheat<'a> {
+    regions: RegionsInRange<'a>,
+}
+
+impl<'a> Future for Preheat<'a> {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let regions = match self.regions.poll()? {
+            Async::Ready(regions) => regions,
+            Async::NotReady => return Ok(Async::NotReady),
+        };
+        let mut region_cache = self.regions.client.inner.region_cache.write().unwrap();
+        for region in regions {
+            region_cache.insert(region);
+        }
+        Ok(Async::Ready(()))
+    }
+}
+
+pub struct GetTimestamp<'a> {
+    client: &'a Client,
+    in_flight: Option<InFlight<'a>>,
+}
+
+impl<'a> GetTimestamp<'a> {
+    fn new(client: &'a Client) -> Self {
+        GetTimestamp {
+            client,
+            in_flight: None,
+        }
+    }
+}
+
+impl<'a> Future for GetTimestamp<'a> {
+    type Item = Timestamp;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        self.client.check_mock_supported("GetTimestamp")?;
+        let _ = &self.client;
+        unimplemented!()
+    }
+}
+
+pub struct StoreVersions<'a> {
+    client: &'a Client,
+    in_flight: Option<InFlight<'a>>,
+}
+
+impl<'a> StoreVersions<'a> {
+    fn new(client: &'a Client) -> Self {
+        StoreVersions {
+            client,
+            in_flight: None,
+        }
+    }
+}
+
+impl<'a> Future for StoreVersions<'a> {
+    type Item = HashMap<u64, String>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        self.client.check_mock_supported("StoreVersions")?;
+        let _ = &self.client;
+        unimplemented!()
+    }
+}
+
+pub struct GcSafepoint<'a> {
+    client: &'a Client,
+    in_flight: Option<InFlight<'a>>,
+}
+
+impl<'a> GcSafepoint<'a> {
+    fn new(client: &'a Client) -> Self {
+        GcSafepoint {
+            client,
+            in_flight: None,
+        }
+    }
+}
+
+impl<'a> Future for GcSafepoint<'a> {
+    type Item = Timestamp;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        #[cfg(feature = "mock")]
+        {
+            // The mock store keeps a single version of each key and never collects.
+            if self.client.inner.mock.is_some() {
+                return Ok(Async::Ready(0.into()));
+            }
+        }
+        let _ = &self.client;
+        unimplemented!()
+    }
+}
+
+pub struct Flush<'a> {
+    client: &'a Client,
+    in_flight: Option<InFlight<'a>>,
+}
+
+impl<'a> Flush<'a> {
+    fn new(client: &'a Client) -> Self {
+        Flush {
+            client,
+            in_flight: None,
+        }
+    }
+}
+
+impl<'a> Future for Flush<'a> {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        // Nothing is buffered: write futures only resolve once TiKV has acknowledged
+        // the write.
+        Ok(Async::Ready(()))
+    }
+}
+
+pub struct HealthCheck<'a> {
+    client: &'a Client,
+    in_flight: Option<InFlight<'a>>,
+}
+
+impl<'a> HealthCheck<'a> {
+    fn new(client: &'a Client) -> Self {
+        HealthCheck {
+            client,
+            in_flight: None,
+        }
+    }
+}
+
+impl<'a> Future for HealthCheck<'a> {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        self.client.check_mock_supported("HealthCheck")?;
+        let _ = &self.client;
+        unimplemented!()
+    }
+}
+
+pub struct PingStore<'a> {
+    client: &'a Client,
+    store_id: u64,
+    // When the probe was sent, to measure the round trip from.
+    sent_at: Option<Instant>,
+    in_flight: Option<InFlight<'a>>,
+}
+
+impl<'a> PingStore<'a> {
+    fn new(client: &'a Client, store_id: u64) -> Self {
+        PingStore {
+            client,
+            store_id,
+            sent_at: None,
+            in_flight: None,
+        }
+    }
+}
+
+impl<'a> Future for PingStore<'a> {
+    type Item = Duration;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.client.check_open(&mut self.in_flight)?;
+        self.client.check_mock_supported("PingStore")?;
+        let _ = &self.client;
+        let _ = &self.store_id;
+        let _ = &self.sent_at;
+        unimplemented!()
+    }
+}
+
+// Counts a request in `Inner::requests_in_flight` from its first poll until it is
+// dropped, so that `Close` waits for it.
+struct InFlight<'a> {
+    inner: &'a Inner,
+}
+
+impl<'a> Drop for InFlight<'a> {
+    fn drop(&mut self) {
+        if self.inner.requests_in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            for task in self.inner.closing.lock().unwrap().drain(..) {
+                task.notify();
+            }
+        }
+    }
+}
+
+pub struct Close {
+    // Dropped once the requests in flight are done.
+    inner: Option<Arc<Inner>>,
+}
+
+impl Future for Close {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        {
+            let inner = match self.inner {
+                Some(ref inner) => inner,
+                None => return Ok(Async::Ready(())),
+            };
+            inner.closed.store(true, Ordering::SeqCst);
+            // Registered before checking the count, so that the last request to finish
+            // either sees the task or is seen to have finished.
+            let mut closing = inner.closing.lock().unwrap();
+            if !closing.iter().any(Task::will_notify_current) {
+                closing.push(task::current());
+            }
+            drop(closing);
+            if inner.requests_in_flight.load(Ordering::SeqCst) > 0 {
+                return Ok(Async::NotReady);
+            }
+        }
+        self.inner = None;
+        Ok(Async::Ready(()))
+    }
+}
+
+// Sends the PD `GetMembers` request to one endpoint, resolving to the cluster id in
+// the header of its response.
+struct PdHandshake {
+    channel: Channel,
+}
+
+impl Future for PdHandshake {
+    type Item = u64;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let _ = &self.channel;
+        unimplemented!()
+    }
+}
+
+type PdFailover = Failover<Box<FnMut(&str) -> PdHandshake + Send>, PdHandshake>;
+
+// Sends the PD `LoadKeyspace` request for the keyspace `name`, resolving to its id,
+// or failing with `KeyspaceNotFound` if PD doesn't know it.
+struct LoadKeyspace {
+    cluster_id: u64,
+    name: String,
+}
+
+impl Future for LoadKeyspace {
+    type Item = u32;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let _ = &self.cluster_id;
+        let _ = &self.name;
+        unimplemented!()
+    }
+}
+
+// Lists the stores of the cluster with the PD `GetAllStores` request and opens a
+// channel to each of them, `concurrency` at a time, resolving once all are ready.
+struct ConnectStores {
+    cluster_id: u64,
+    concurrency: usize,
+}
+
+impl Future for ConnectStores {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let _ = &self.cluster_id;
+        let _ = &self.concurrency;
+        unimplemented!()
+    }
+}
+
+pub struct Connect {
+    config: Config,
+    handshake: Option<Timeout<PdFailover>>,
+    cluster_id: Option<u64>,
+    load_keyspace: Option<Timeout<LoadKeyspace>>,
+    keyspace_id: Option<u32>,
+    connect_stores: Option<Timeout<ConnectStores>>,
+}
+
+impl Connect {
+    fn new(config: Config) -> Self {
+        Connect {
+            config,
+            handshake: None,
+            cluster_id: None,
+            load_keyspace: None,
+            keyspace_id: None,
+            connect_stores: None,
+        }
+    }
+}
+
+impl Future for Connect {
+    type Item = Client;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if self.handshake.is_none() {
+            let security_mgr = SecurityManager::load(&self.config)?;
+            let env = Arc::new(Environment::new(1));
+            let config = self.config.clone();
+            let handshake = move |addr: &str| {
+                let cb = security::channel_builder(Arc::clone(&env), &config);
+                PdHandshake {
+                    channel: security_mgr.connect(cb, addr),
+                }
+            };
+            let endpoints = dns::resolve_endpoints(&self.config.pd_endpoints);
+            let failover: PdFailover =
+                Failover::new(endpoints, self.config.retry, Box::new(handshake));
+            self.handshake = Some(Timeout::new(failover, self.config.timeout));
+        }
+        let cluster_id = match self.cluster_id {
+            Some(cluster_id) => cluster_id,
+            None => match self.handshake.as_mut().unwrap().poll()? {
+                Async::Ready(cluster_id) => {
+                    self.cluster_id = Some(cluster_id);
+                    cluster_id
+                }
+                Async::NotReady => return Ok(Async::NotReady),
+            },
+        };
+        if let (Some(ref name), None) = (&self.config.keyspace, self.keyspace_id) {
+            if self.load_keyspace.is_none() {
+                let load = LoadKeyspace {
+                    cluster_id,
+                    name: name.clone(),
+                };
+                self.load_keyspace = Some(Timeout::new(load, self.config.timeout));
+            }
+            match self.load_keyspace.as_mut().unwrap().poll()? {
+                Async::Ready(keyspace_id) => self.keyspace_id = Some(keyspace_id),
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+        if let Some(concurrency) = self.config.connect_concurrency {
+            if self.connect_stores.is_none() {
+                let connect = ConnectStores {
+                    cluster_id,
+                    concurrency,
+                };
+                self.connect_stores = Some(Timeout::new(connect, self.config.timeout));
+            }
+            if let Async::NotReady = self.connect_stores.as_mut().unwrap().poll()? {
+                return Ok(Async::NotReady);
+            }
+        }
+        Ok(Async::Ready(Client {
+            inner: Arc::new(Inner {
+                config: self.config.clone(),
+                cluster_id,
+                keyspace_id: self.keyspace_id,
+                region_cache: RwLock::new(RegionCache::new(self.config.region_cache_ttl)),
+                closed: AtomicBool::new(false),
+                requests_in_flight: AtomicUsize::new(0),
+                closing: Mutex::new(Vec::new()),
+                reads_in_flight: Mutex::new(HashMap::new()),
+                recent_writes: Mutex::new(RecentWrites::default()),
+                #[cfg(feature = "mock")]
+                mock: None,
+            }),
+            cf: None,
+        }))
+    }
+}
+
+struct Inner {
+    config: Config,
+    cluster_id: u64,
+    keyspace_id: Option<u32>,
+    region_cache: RwLock<RegionCache>,
+    closed: AtomicBool,
+    // The requests started and not yet resolved or dropped, and the `Close`s waiting
+    // for them.
+    requests_in_flight: AtomicUsize,
+    closing: Mutex<Vec<Task>>,
+    // The `Get`s waiting on a coalesced read.
+    reads_in_flight: Mutex<ReadWaiters>,
+    recent_writes: Mutex<RecentWrites>,
+    #[cfg(feature = "mock")]
+    mock: Option<Arc<MockStore>>,
+}
+
+/// A raw TiKV client.
+///
+/// `Get`, `BatchGet`, `Put`, `BatchPut`, `Delete`, `BatchDelete` and `Scan` requests
+/// that fail with `NotLeader`, `StaleEpoch` or `RegionNotFound` drop the stale region
+/// from the cache, re-resolve it from PD and are retried, up to the attempts allowed
+/// by `Config::retry`.
+///
+/// The PD and store connections and the region cache live behind an `Arc`, so
+/// cloning a `Client` is cheap and does not re-establish any connection; all clones
+/// share the same underlying state and can be used from multiple threads.
+#[derive(Clone)]
+pub struct Client {
+    inner: Arc<Inner>,
+    cf: Option<ColumnFamily>,
+}
+
+impl Client {
+    #![cfg_attr(feature = "cargo-clippy", allow(new_ret_no_self))]
+    pub fn new(config: &Config) -> Connect {
+        Connect::new(config.clone())
+    }
+
+    /// Creates a client backed by an in-memory store instead of a TiKV cluster, for
+    /// testing code built on top of it. Reads, writes, scans, the range requests and
+    /// `compare_and_swap` resolve immediately against the store, which is a single
+    /// region as far as `get_region` and `regions_in_range` are concerned; TTLs are
+    /// ignored and the other requests that need PD fail with `Error::MockUnsupported`.
+    #[cfg(feature = "mock")]
+    pub fn new_mock() -> Client {
+        Client::new_mock_with(Config::default())
+    }
+
+    #[cfg(feature = "mock")]
+    fn new_mock_with(config: Config) -> Client {
+        Client::new_mock_in(Arc::new(MockStore::default()), config)
+            .expect("a fresh mock store has no keyspaces")
+    }
+
+    // Like `new_mock_with`, but sharing `mock` with other clients, e.g. of another
+    // keyspace. `Config::keyspace` is looked up in the store instead of in PD.
+    #[cfg(feature = "mock")]
+    fn new_mock_in(mock: Arc<MockStore>, config: Config) -> Result<Client, Error> {
+        let keyspace_id = match config.keyspace {
+            Some(ref name) => match mock.keyspace_id(name) {
+                Some(keyspace_id) => Some(keyspace_id),
+                None => return Err(Error::KeyspaceNotFound(name.clone())),
+            },
+            None => None,
+        };
+        Ok(Client {
+            inner: Arc::new(Inner {
+                config,
+                cluster_id: 0,
+                keyspace_id,
+                region_cache: RwLock::new(RegionCache::new(None)),
+                closed: AtomicBool::new(false),
+                requests_in_flight: AtomicUsize::new(0),
+                closing: Mutex::new(Vec::new()),
+                reads_in_flight: Mutex::new(HashMap::new()),
+                recent_writes: Mutex::new(RecentWrites::default()),
+                mock: Some(mock),
+            }),
+            cf: None,
+        })
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.inner.config
+    }
+
+    /// The id of the cluster, as reported by PD while connecting.
+    pub fn cluster_id(&self) -> u64 {
+        self.inner.cluster_id
+    }
+
+    /// The id of the keyspace set with `Config::keyspace`, if any.
+    pub fn keyspace_id(&self) -> Option<u32> {
+        self.inner.keyspace_id
+    }
+
+    /// Resolves to the version reported by each TiKV store, keyed by store id, e.g.
+    /// to check whether the cluster is recent enough for `compare_and_swap`.
+    pub fn store_version(&self) -> StoreVersions {
+        StoreVersions::new(self)
+    }
+
+    /// Resolves to the cluster's GC safepoint, fetched from PD. Versions older than
+    /// the safepoint may have been garbage collected, so reads below it can miss data.
+    pub fn gc_safepoint(&self) -> GcSafepoint {
+        GcSafepoint::new(self)
+    }
+
+    /// Marks the client as closed and resolves once the requests already polled
+    /// through any clone of the client have resolved or been dropped. Requests polled
+    /// for the first time afterwards fail with `Error::Closed`. The PD and TiKV
+    /// channels are closed once the last clone of the client is dropped.
+    pub fn close(self) -> Close {
+        Close {
+            inner: Some(self.inner),
+        }
+    }
+
+    /// Makes requests sent through the returned client use `cf` unless they set
+    /// their own with `cf(...)`. The connections are shared with `self`.
+    pub fn with_cf(self, cf: impl Into<ColumnFamily>) -> Client {
+        Client {
+            cf: Some(cf.into()),
+            ..self
+        }
+    }
+
+    /// Resolves to the value of `key`, or `None` if the key doesn't exist.
+    pub fn get(&self, key: impl AsRef<Key>) -> Get {
+        Get::new(self, key.as_ref().clone())
+    }
+
+    /// Reads the value of `key` as a stream of chunks of at most `chunk_size` bytes, so
+    /// that a large value can be consumed piece by piece. A value no larger than
+    /// `chunk_size` is yielded as a single chunk, and a missing key yields none.
+    ///
+    /// TiKV answers a `RawGet` with a single message, so the value is still received
+    /// as a whole before the first chunk is yielded. The chunks are slices of that
+    /// one buffer rather than copies of it.
+    pub fn get_stream(&self, key: impl AsRef<Key>) -> GetStream {
+        GetStream::new(self.get(key))
+    }
+
+    /// Reads the remaining time-to-live of `key`, or `None` if it never expires.
+    pub fn get_key_ttl(&self, key: impl AsRef<Key>) -> GetKeyTtl {
+        GetKeyTtl::new(self, key.as_ref().clone())
+    }
+
+    /// Reads the remaining time-to-live of each of `keys` with one `RawBatchGetKeyTTL`
+    /// per region, resolving to one entry per key in the order given. The entry is
+    /// `None` if the key doesn't exist or never expires.
+    pub fn batch_get_key_ttl(&self, keys: impl AsRef<[Key]>) -> BatchGetKeyTtl {
+        BatchGetKeyTtl::new(self, keys.as_ref().to_vec())
+    }
+
+    /// Gets the values of `keys`. Keys that don't exist are omitted from the result,
+    /// and the pairs come back in no particular order; see `BatchGet::preserve_order`.
+    pub fn batch_get(&self, keys: impl AsRef<[Key]>) -> BatchGet {
+        BatchGet::new(self, keys.as_ref().to_vec())
+    }
+
+    pub fn put(&self, key: impl Into<Key>, value: impl Into<Value>) -> Put {
+        Put::new(self, key.into(), value.into())
+    }
+
+    /// Puts `pairs` into TiKV.
+    ///
+    /// The pairs are grouped by the region owning each key, using the region cache,
+    /// and one `RawBatchPut` is sent per region, in parallel up to `max_concurrency`.
+    /// When several pairs have the same key, the last one wins: the others are
+    /// dropped before anything is sent.
+    pub fn batch_put(&self, pairs: impl IntoIterator<Item = impl Into<KvPair>>) -> BatchPut {
+        BatchPut::new(self, pairs.into_iter().map(Into::into).collect())
+    }
+
+    /// Returns a `Sink` for streaming pairs into TiKV in batches; see `PutSink`.
+    pub fn put_sink(&self) -> PutSink {
+        PutSink::new(self)
+    }
+
+    pub fn delete(&self, key: impl AsRef<Key>) -> Delete {
+        Delete::new(self, key.as_ref().clone())
+    }
+
+    pub fn batch_delete(&self, keys: impl AsRef<[Key]>) -> BatchDelete {
+        BatchDelete::new(self, keys.as_ref().to_vec())
+    }
+
+    /// Scans at most `limit` pairs in `range`, in ascending key order.
+    ///
+    /// Included, excluded and unbounded ends are honored exactly, so `(Unbounded,
+    /// Unbounded)` scans the whole keyspace. A range whose start is past its end
+    /// yields no pairs rather than an error.
+    pub fn scan(&self, range: impl KeyRange, limit: u32) -> Scan {
+        Scan::new(self, Self::extract_range(&range.into_bounds()), limit)
+    }
+
+    /// Continues a scan from `token`, returned by a `ResumableScan`, with the column
+    /// family, direction and prefix filter of the original scan. Its `take_while` and
+    /// `map_value` must be set again.
+    pub fn scan_resume(&self, token: ScanToken, limit: u32) -> Scan {
+        let mut scan = Scan::new(self, Self::extract_range(&(token.start, token.end)), limit);
+        scan.cf = token.cf;
+        scan.reverse = token.reverse;
+        scan.key_only = token.key_only;
+        scan.filter_prefix = token.filter_prefix;
+        scan
+    }
+
+    /// Scans at most `limit` pairs from `start`, inclusive, with no upper bound.
+    pub fn scan_from(&self, start: impl AsRef<Key>, limit: u32) -> Scan {
+        Scan::from_key(self, start.as_ref().clone(), limit)
+    }
+
+    /// Pages through `range`, `page_size` pairs at a time. Each page starts right
+    /// after the last key of the previous one.
+    pub fn paginate(&self, range: impl KeyRange, page_size: u32) -> Paginator {
+        Paginator::new(self, Self::extract_range(&range.into_bounds()), page_size)
+    }
+
+    /// Scans the keys starting with `prefix`.
+    pub fn scan_prefix(&self, prefix: impl AsRef<Key>, limit: u32) -> Scan {
+        let start = prefix.as_ref().clone();
+        let end = start.prefix_end().unwrap_or_default();
+        Scan::new(self, (start, end), limit)
+    }
+
+    /// Scans `range` for keys only, without fetching their values.
+    pub fn scan_keys(&self, range: impl KeyRange, limit: u32) -> ScanKeys {
+        ScanKeys::new(self, Self::extract_range(&range.into_bounds()), limit)
+    }
+
+    /// Scans each of `ranges`, returning at most `each_limit` pairs per range. The
+    /// results are concatenated in the order the ranges are given, and the pairs of
+    /// each range are in ascending key order unless `BatchScan::reverse` is set.
+    pub fn batch_scan<R: KeyRange>(
+        &self,
+        ranges: impl IntoIterator<Item = R>,
+        each_limit: u32,
+    ) -> BatchScan {
+        BatchScan::new(
+            self,
+            ranges
+                .into_iter()
+                .map(|range| Self::extract_range(&range.into_bounds()))
+                .collect(),
+            each_limit,
+        )
+    }
+
+    /// Deletes the keys in `range`, resolving to the number of keys deleted once every
+    /// region overlapping the range has been handled. Regions are deleted from
+    /// concurrently, up to `max_concurrency` at a time.
+    pub fn delete_range(&self, range: impl KeyRange) -> DeleteRange {
+        DeleteRange::new(self, Self::extract_range(&range.into_bounds()))
+    }
+
+    /// Physically removes the data in `range` from every store holding a region that
+    /// overlaps it, resolving once all of them acknowledge.
+    ///
+    /// Unlike `delete_range` this writes no tombstones and bypasses MVCC entirely. It
+    /// cannot be undone, and must not be used on ranges that live transactions may
+    /// still read or write.
+    pub fn unsafe_destroy_range(&self, range: impl KeyRange) -> UnsafeDestroyRange {
+        UnsafeDestroyRange::new(self, Self::extract_range(&range.into_bounds()))
+    }
+
+    /// Compacts every region overlapping `range` and resolves once all the touched
+    /// stores have acknowledged. An unbounded range compacts the entire keyspace.
+    pub fn compact_range(&self, range: impl KeyRange) -> CompactRange {
+        CompactRange::new(self, Self::extract_range(&range.into_bounds()))
+    }
+
+    /// Computes the checksum of `range` with a coprocessor checksum request per
+    /// region, merging the per-region results with `ChecksumResult::merge`.
+    pub fn checksum(&self, range: impl KeyRange) -> Checksum {
+        Checksum::new(self, Self::extract_range(&range.into_bounds()))
+    }
+
+    /// Atomically sets `key` to `new_value` if its current value equals `previous_value`.
+    ///
+    /// `previous_value = None` only sets the key if it is currently absent. The future
+    /// resolves to the prior value and whether the swap happened. Requires the TiKV
+    /// cluster to run with `api-version = 2`, otherwise it fails with `Error::CasUnsupported`.
+    pub fn compare_and_swap(
+        &self,
+        key: impl Into<Key>,
+        previous_value: Option<Value>,
+        new_value: impl Into<Value>,
+    ) -> CompareAndSwap {
+        CompareAndSwap::new(self, key.into(), previous_value, new_value.into())
+    }
+
+    /// Sets `key` to `value` only if the key doesn't exist yet, resolving to whether
+    /// it was set. This is a `compare_and_swap` expecting no previous value, so it
+    /// has the same `api-version = 2` requirement.
+    pub fn put_if_absent(&self, key: impl Into<Key>, value: impl Into<Value>) -> PutIfAbsent {
+        PutIfAbsent::new(self, key.into(), value.into())
+    }
+
+    /// Uploads `sst` to the stores of the regions it covers through the import service
+    /// and ingests it into those regions.
+    pub fn ingest(&self, sst: SstFile) -> Ingest {
+        Ingest::new(self, sst)
+    }
+
+    /// Looks up the region owning `key`, from the region cache or else from PD,
+    /// updating the cache.
+    pub fn get_region(&self, key: impl AsRef<Key>) -> GetRegion {
+        GetRegion::new(self, key.as_ref().clone())
+    }
+
+    /// Lists the regions covering `range` as currently known to PD, in key order, so
+    /// work can be partitioned one task per region. The peers carry their store
+    /// addresses for locality-aware scheduling.
+    pub fn regions_in_range(&self, range: impl KeyRange) -> RegionsInRange {
+        RegionsInRange::new(self, Self::extract_range(&range.into_bounds()))
+    }
+
+    /// Loads the regions covering `range` from PD into the region cache, resolving once
+    /// they are all cached. Calling it before starting many requests over the range
+    /// saves them from each looking up their region in PD at the same time.
+    pub fn preheat(&self, range: impl KeyRange) -> Preheat {
+        Preheat {
+            regions: self.regions_in_range(range),
+        }
+    }
+
+    /// Allocates a timestamp from the PD timestamp oracle.
+    ///
+    /// Concurrent calls are batched into a single `Tso` stream request to PD.
+    pub fn get_timestamp(&self) -> GetTimestamp {
+        GetTimestamp::new(self)
+    }
+
+    /// Resolves once every buffered write has been acknowledged by the stores.
+    ///
+    /// The client currently sends every write as soon as its future is polled, so
+    /// there is never anything to flush and this resolves immediately.
+    pub fn flush(&self) -> Flush {
+        Flush::new(self)
+    }
+
+    /// Registers `f` to be called whenever a `NotLeader` or `StaleEpoch` response
+    /// leads the client to re-resolve a region, with the region's leader before and
+    /// after. It runs with the region cache locked, so it should return quickly.
+    pub fn on_region_change(&self, f: impl Fn(RegionChange) + Send + Sync + 'static) {
+        self.inner
+            .region_cache
+            .write()
+            .unwrap()
+            .add_listener(Box::new(f));
+    }
+
+    /// Checks that PD answers a members request and that at least one store is
+    /// reachable, failing with `Error::Unhealthy` otherwise. No data is read, so it is
+    /// cheap enough to back a readiness probe.
+    pub fn health_check(&self) -> HealthCheck {
+        HealthCheck::new(self)
+    }
+
+    /// Resolves to the round-trip time of a gRPC health check sent to the store with
+    /// id `store_id`, to single out a slow node. Fails with `Error::StoreNotFound` if
+    /// PD doesn't know the store. The time to look up the store's address and connect
+    /// to it is not included.
+    pub fn ping_store(&self, store_id: u64) -> PingStore {
+        PingStore::new(self, store_id)
+    }
+
+    // Splits the encoded `range` along the regions it overlaps, in key order.
+    fn split_range(&self, range: &(Key, Key)) -> Result<Vec<(Key, Key)>, Error> {
+        let mut parts = Vec::new();
+        let mut start = range.0.clone();
+        loop {
+            let region = self.locate(&start)?;
+            let end = region.end_key;
+            if end.is_empty() || (!range.1.is_empty() && end >= range.1) {
+                parts.push((start, range.1.clone()));
+                return Ok(parts);
+            }
+            parts.push((start, end.clone()));
+            start = end;
+        }
+    }
+
+    // The stores holding a peer of a region overlapping the encoded `range`, by one
+    // of these peers, in store id order.
+    fn stores_in(&self, range: &(Key, Key)) -> Result<Vec<Peer>, Error> {
+        let mut stores = BTreeMap::new();
+        for part in self.split_range(range)? {
+            let region = self.locate(&part.0)?;
+            for peer in region.peers {
+                stores.entry(peer.store_id).or_insert(peer);
+            }
+        }
+        Ok(stores.into_values().collect())
+    }
+
+    // Groups `items` by the region of their encoded keys, in key order.
+    fn group_by_region<T, F>(&self, items: Vec<T>, key: F) -> Result<Vec<Vec<T>>, Error>
+    where
+        F: Fn(&T) -> &Key,
+    {
+        let mut regions: BTreeMap<Key, Vec<T>> = BTreeMap::new();
+        for item in items {
+            let region = self.locate(key(&item))?;
+            regions.entry(region.start_key).or_default().push(item);
+        }
+        Ok(regions.into_values().collect())
+    }
+
+    // Like `locate`, for the region containing the keys right below `key`, see
+    // `RegionCache::get_before`.
+    fn locate_before(&self, key: &Key) -> Result<Region, Error> {
+        if let Some(region) = self.inner.region_cache.read().unwrap().get_before(key) {
+            return Ok(region.clone());
+        }
+        #[cfg(feature = "mock")]
+        {
+            if let Some(ref mock) = self.inner.mock {
+                let region = mock.region_before(key);
+                self.inner
+                    .region_cache
+                    .write()
+                    .unwrap()
+                    .insert(region.clone());
+                return Ok(region);
+            }
+        }
+        unimplemented!()
+    }
+
+    // The region containing `key`, from the region cache or else from PD, which
+    // updates the cache.
+    fn locate(&self, key: &Key) -> Result<Region, Error> {
+        if let Some(region) = self.inner.region_cache.read().unwrap().get(key) {
+            return Ok(region.clone());
+        }
+        #[cfg(feature = "mock")]
+        {
+            if let Some(ref mock) = self.inner.mock {
+                let region = mock.region(key);
+                self.inner
+                    .region_cache
+                    .write()
+                    .unwrap()
+                    .insert(region.clone());
+                return Ok(region);
+            }
+        }
+        unimplemented!()
+    }
+
+    // Bounds a request created now by `deadline` and by `Config::timeout`, whichever
+    // expires first.
+    fn request_timeout(&self, deadline: Option<Instant>) -> Deadline {
+        let timeout = self
+            .inner
+            .config
+            .timeout
+            .map(|timeout| Instant::now() + timeout);
+        Deadline::new(match (deadline, timeout) {
+            (Some(deadline), Some(timeout)) => Some(cmp::min(deadline, timeout)),
+            (deadline, timeout) => deadline.or(timeout),
+        })
+    }
+
+    // Fails a request first polled after `close` with `Error::Closed`, and otherwise
+    // counts it in flight until `in_flight` is dropped along with the request.
+    fn check_open<'a>(&'a self, in_flight: &mut Option<InFlight<'a>>) -> Result<(), Error> {
+        if in_flight.is_some() {
+            return Ok(());
+        }
+        // Counted before checking, so that `Close` can't miss it.
+        self.inner.requests_in_flight.fetch_add(1, Ordering::SeqCst);
+        let request = InFlight { inner: &self.inner };
+        if self.inner.closed.load(Ordering::SeqCst) {
+            return Err(Error::Closed);
+        }
+        *in_flight = Some(request);
+        Ok(())
+    }
+
+    // Fails `request` on a mock client, whose store can't serve it.
+    fn check_mock_supported(&self, request: &'static str) -> Result<(), Error> {
+        #[cfg(feature = "mock")]
+        {
+            if self.inner.mock.is_some() {
+                return Err(Error::MockUnsupported(request));
+            }
+        }
+        let _ = request;
+        Ok(())
+    }
+
+    fn check_key(&self, key: &Key) -> Result<(), Error> {
+        let max = self
+            .inner
+            .config
+            .max_key_size
+            .unwrap_or(DEFAULT_MAX_KEY_SIZE);
+        if key.len() > max {
+            return Err(Error::KeyTooLong {
+                len: key.len(),
+                max,
+            });
+        }
+        Ok(())
+    }
+
+    // `index` is the position of the pair within a batch.
+    fn check_value(&self, value: &Value, index: Option<usize>) -> Result<(), Error> {
+        let max = self
+            .inner
+            .config
+            .max_value_size
+            .unwrap_or(DEFAULT_MAX_VALUE_SIZE);
+        if value.len() > max {
+            return Err(Error::ValueTooLarge {
+                index,
+                len: value.len(),
+                max,
+            });
+        }
+        Ok(())
+    }
+
+    // The key sent to TiKV for the user key `key`, per `Config::api_version` and the
+    // keyspace of the client.
+    fn encode_key(&self, key: &Key) -> Key {
+        let keyspace_id = self.inner.keyspace_id.unwrap_or(0);
+        self.inner.config.api_version.encode_key(keyspace_id, key)
+    }
+
+    fn encode_range(&self, range: &(Key, Key)) -> (Key, Key) {
+        let keyspace_id = self.inner.keyspace_id.unwrap_or(0);
+        self.inner
+            .config
+            .api_version
+            .encode_range(keyspace_id, range.clone())
+    }
+
+    // Turns the keys of `pairs` returned by TiKV back into user keys.
+    fn decode_pairs(&self, pairs: Vec<KvPair>) -> Result<Vec<KvPair>, Error> {
+        let keyspace_id = self.inner.keyspace_id.unwrap_or(0);
+        let api_version = self.inner.config.api_version;
+        pairs
+            .into_iter()
+            .map(|pair| {
+                let (key, value) = pair.into_inner();
+                Ok(KvPair::new(
+                    api_version.decode_key(keyspace_id, key)?,
+                    value,
+                ))
+            })
+            .collect()
+    }
+
+    // Returns the limit a scan asking for `limit` pairs runs with, per
+    // `Config::max_scan_limit`.
+    fn check_scan_limit(&self, limit: u32) -> Result<u32, Error> {
+        match self.inner.config.max_scan_limit {
+            Some(max) if limit > max => {
+                if self.inner.config.clamp_scan_limit {
+                    Ok(max)
+                } else {
+                    Err(Error::ScanLimitExceeded { limit, max })
+                }
+            }
+            _ => Ok(limit),
+        }
+    }
+
+    // Converts `range` into the half-open `[start, end)` form TiKV expects, where an
+    // empty `end` means unbounded.
+    fn extract_range(range: &impl RangeBounds<Key>) -> (Key, Key) {
+        let start = match range.start_bound() {
+            Bound::Included(key) => key.clone(),
+            Bound::Excluded(key) => key.next(),
+            Bound::Unbounded => Key::default(),
+        };
+        let end = match range.end_bound() {
+            Bound::Included(key) => key.next(),
+            // Nothing sorts before the empty key, so the range is empty.
+            Bound::Excluded(key) if key.is_empty() => return (vec![0].into(), vec![0].into()),
+            Bound::Excluded(key) => key.clone(),
+            Bound::Unbounded => Key::default(),
+        };
+        (start, end)
+    }
+
+    fn is_empty_range(range: &(Key, Key)) -> bool {
+        !range.1.is_empty() && range.0 >= range.1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+    #[cfg(feature = "mock")]
+    use std::sync::{Arc, Mutex};
+    #[cfg(feature = "mock")]
+    use std::thread;
+    #[cfg(feature = "mock")]
+    use std::time::{Duration, Instant};
+
+    #[cfg(feature = "mock")]
+    use futures::executor::{self, Notify, Spawn};
+    #[cfg(feature = "mock")]
+    use futures::{Async, Future, Poll, Sink, Stream};
+
+    use super::{mark_regions, Client};
+    #[cfg(feature = "mock")]
+    use super::{BatchGet, ReadPolicy, Scan, ScanResult, ScanToken, SstFile};
+    #[cfg(feature = "mock")]
+    use mock::MockStore;
+    #[cfg(feature = "mock")]
+    use Value;
+    #[cfg(feature = "mock")]
+    use {ApiVersion, Backoff, Config, Error, Priority};
+    use {Key, KvPair};
+
+    fn key(s: &str) -> Key {
+        s.as_bytes().to_vec().into()
+    }
+
+    fn range(start: &str, end: &str) -> (Key, Key) {
+        (key(start), key(end))
+    }
+
+    #[test]
+    fn extract_range_honors_each_bound() {
+        use self::Bound::*;
+
+        let cases = vec![
+            ((Included(key("a")), Included(key("c"))), range("a", "c\0")),
+            ((Included(key("a")), Excluded(key("c"))), range("a", "c")),
+            ((Included(key("a")), Unbounded), range("a", "")),
+            (
+                (Excluded(key("a")), Included(key("c"))),
+                range("a\0", "c\0"),
+            ),
+            ((Excluded(key("a")), Excluded(key("c"))), range("a\0", "c")),
+            ((Excluded(key("a")), Unbounded), range("a\0", "")),
+            ((Unbounded, Included(key("c"))), range("", "c\0")),
+            ((Unbounded, Excluded(key("c"))), range("", "c")),
+            ((Unbounded, Unbounded), range("", "")),
+        ];
+        for (bounds, expected) in cases {
+            assert_eq!(Client::extract_range(&bounds), expected, "{:?}", bounds);
+        }
+        assert_eq!(Client::extract_range(&(key("a")..)), range("a", ""));
+        assert_eq!(Client::extract_range(&..), range("", ""));
+    }
+
+    #[test]
+    fn excluded_empty_end_is_empty() {
+        let bounds = (Bound::Unbounded, Bound::Excluded(Key::default()));
+        let range = Client::extract_range(&bounds);
+        assert_eq!(range, (vec![0].into(), vec![0].into()));
+        assert!(Client::is_empty_range(&range));
+    }
+
+    #[test]
+    fn is_empty_range() {
+        assert!(!Client::is_empty_range(&range("", "")));
+        assert!(!Client::is_empty_range(&range("a", "")));
+        assert!(!Client::is_empty_range(&range("a", "b")));
+        assert!(Client::is_empty_range(&range("a", "a")));
+        assert!(Client::is_empty_range(&range("b", "a")));
+        let inverted =
+            Client::extract_range(&(Bound::Excluded(key("a")), Bound::Included(key("a"))));
+        assert!(Client::is_empty_range(&inverted));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn scan_honors_bounds() {
+        let client = Client::new_mock();
+        for k in &["a", "b", "c"] {
+            client.put(key(k), *k).wait().unwrap();
+        }
+        let scan = |bounds: (Bound<Key>, Bound<Key>)| -> Vec<Key> {
+            let pairs = client.scan(bounds, 10).wait().unwrap();
+            pairs.iter().map(|pair| pair.key().clone()).collect()
+        };
+        let all = vec![key("a"), key("b"), key("c")];
+        assert_eq!(scan((Bound::Unbounded, Bound::Unbounded)), all);
+        assert_eq!(
+            scan((Bound::Excluded(key("a")), Bound::Excluded(key("c")))),
+            vec![key("b")]
+        );
+        assert_eq!(
+            scan((Bound::Included(key("a")), Bound::Included(key("c")))),
+            all
+        );
+        assert!(scan((Bound::Included(key("c")), Bound::Included(key("a")))).is_empty());
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn scan_prefix_carries_past_trailing_ff() {
+        let client = Client::new_mock();
+        let bytes = |bytes: &[u8]| -> Key { bytes.to_vec().into() };
+        let all = vec![
+            bytes(b"a"),
+            bytes(&[b'a', 0xFF]),
+            bytes(&[b'a', 0xFF, 0x00]),
+            bytes(&[b'a', 0xFF, 0xFF]),
+            bytes(b"b"),
+            bytes(&[0xFF, 0xFF]),
+            bytes(&[0xFF, 0xFF, 0x01]),
+        ];
+        for k in &all {
+            client.put(k.clone(), "v").wait().unwrap();
+        }
+        let scan = |prefix: Key| -> Vec<Key> {
+            let pairs = client.scan_prefix(prefix, 10).wait().unwrap();
+            pairs.into_iter().map(|(key, _)| key).collect()
+        };
+        assert_eq!(scan(bytes(&[b'a', 0xFF])), all[1..4].to_vec());
+        assert_eq!(scan(bytes(&[0xFF, 0xFF])), all[5..].to_vec());
+        assert_eq!(scan(Key::default()), all);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn reverse_scan_honors_inclusive_end() {
+        let client = Client::new_mock();
+        for k in &["0", "a", "m", "z", "za"] {
+            client.put(key(k), *k).wait().unwrap();
+        }
+        let keys =
+            |pairs: ScanResult| -> Vec<Key> { pairs.into_iter().map(|(key, _)| key).collect() };
+        let exclusive = client.scan(key("a")..key("z"), 10).reverse();
+        assert_eq!(keys(exclusive.wait().unwrap()), vec![key("m"), key("a")]);
+        let inclusive = client.scan(key("a")..=key("z"), 10).reverse();
+        assert_eq!(
+            keys(inclusive.wait().unwrap()),
+            vec![key("z"), key("m"), key("a")]
+        );
+        let first = client.scan(key("a")..=key("z"), 1).reverse();
+        assert_eq!(keys(first.wait().unwrap()), vec![key("z")]);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn scan_stream_pages_through_range() {
+        let client = Client::new_mock();
+        for k in &["a", "b", "c", "d", "e"] {
+            client.put(key(k), *k).wait().unwrap();
+        }
+        let keys = |scan: Scan| -> Vec<Key> {
+            let stream = scan.into_stream().batch_size(2);
+            let pairs = stream.collect().wait().unwrap();
+            pairs.into_iter().map(|pair| pair.into_inner().0).collect()
+        };
+        let expected: Vec<Key> = ["b", "c", "d", "e"].iter().map(|k| key(k)).collect();
+        assert_eq!(keys(client.scan(key("b").., 10)), expected);
+        let reversed: Vec<Key> = expected.iter().rev().cloned().collect();
+        assert_eq!(keys(client.scan(key("b").., 10).reverse()), reversed);
+        assert_eq!(keys(client.scan(key("b").., 3)), &expected[..3]);
+        assert!(keys(client.scan(key("f").., 10)).is_empty());
+    }
+
+    #[test]
+    fn region_markers_follow_responses() {
+        use super::ScanItem::{Pair, RegionBoundary};
+
+        let pairs: Vec<KvPair> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|k| KvPair::new(key(k), (*k).into()))
+            .collect();
+        let pair = |i: usize| Pair(pairs[i].clone());
+        // Region 1 answers twice in a row, region 2 without pairs, region 3 last.
+        let starts = [(0, 1), (2, 1), (3, 2), (3, 3), (4, 4)];
+        assert_eq!(
+            mark_regions(pairs.clone(), &starts),
+            vec![
+                RegionBoundary(1),
+                pair(0),
+                pair(1),
+                pair(2),
+                RegionBoundary(3),
+                pair(3),
+            ]
+        );
+        assert!(mark_regions(Vec::new(), &starts).is_empty());
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn unordered_parallel_scans_regions_concurrently() {
+        let client = Client::new_mock();
+        let mock = client.inner.mock.as_ref().unwrap();
+        mock.split(&[key("h"), key("q")], &[1, 2, 3]);
+        for k in &["a", "b", "i", "j", "r", "s"] {
+            client.put(key(k), *k).wait().unwrap();
+        }
+        mock.pause();
+        let scan = client.scan(key("b").., 10).unordered_parallel(2);
+        let mut scan = executor::spawn(scan);
+        assert_eq!(poll_once(&mut scan).unwrap(), Async::NotReady);
+        assert_eq!(mock.parked(), 2);
+        mock.resume();
+        let mut keys: Vec<Key> = scan
+            .wait_future()
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        keys.sort();
+        assert_eq!(keys, vec![key("b"), key("i"), key("j"), key("r"), key("s")]);
+        assert_eq!(mock.requests("RawScan"), vec![2, 3, 4]);
+
+        let pairs = client.scan(.., 3).unordered_parallel(3).wait().unwrap();
+        assert_eq!(pairs.len(), 3);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn batch_scan_merges_region_sub_scans() {
+        let client = Client::new_mock();
+        let mock = client.inner.mock.as_ref().unwrap();
+        mock.split(&[key("h"), key("q")], &[1, 2, 3]);
+        for k in &["a", "b", "i", "j", "r", "s"] {
+            client.put(key(k), *k).wait().unwrap();
+        }
+        let keys = |pairs: Vec<KvPair>| -> Vec<Key> {
+            pairs.into_iter().map(|pair| pair.into_inner().0).collect()
+        };
+        let ranges = vec![key("a")..key("z"), key("i")..key("k")];
+        mock.pause();
+        let batch_scan = client.batch_scan(ranges.clone(), 3).max_concurrency(2);
+        let mut batch_scan = executor::spawn(batch_scan);
+        assert_eq!(poll_once(&mut batch_scan).unwrap(), Async::NotReady);
+        assert_eq!(mock.parked(), 2);
+        mock.resume();
+        assert_eq!(
+            keys(batch_scan.wait_future().unwrap()),
+            vec![key("a"), key("b"), key("i"), key("i"), key("j")]
+        );
+        assert_eq!(mock.requests("RawScan"), vec![2, 3, 4, 3]);
+
+        let reversed = client.batch_scan(ranges, 3).reverse().wait().unwrap();
+        assert_eq!(
+            keys(reversed),
+            vec![key("s"), key("r"), key("j"), key("j"), key("i")]
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn reverse_batch_scan_limits_each_overlapping_range() {
+        let client = Client::new_mock();
+        for k in &["a", "b", "c", "d", "e"] {
+            client.put(key(k), *k).wait().unwrap();
+        }
+        let ranges = vec![key("a")..key("e"), key("c")..key("f")];
+        let pairs = client.batch_scan(ranges, 2).reverse().wait().unwrap();
+        let keys: Vec<Key> = pairs.into_iter().map(|pair| pair.into_inner().0).collect();
+        assert_eq!(keys, vec![key("d"), key("c"), key("e"), key("d")]);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn batch_scan_dedup_keeps_first_occurrence() {
+        let client = Client::new_mock();
+        for k in &["a", "b", "c", "d"] {
+            client.put(key(k), *k).wait().unwrap();
+        }
+        let ranges = vec![key("a")..=key("c"), key("b")..=key("d")];
+        let keys = |pairs: Vec<KvPair>| -> Vec<Key> {
+            pairs.into_iter().map(|pair| pair.into_inner().0).collect()
+        };
+        let pairs = client.batch_scan(ranges.clone(), 10).wait().unwrap();
+        assert_eq!(
+            keys(pairs),
+            vec![key("a"), key("b"), key("c"), key("b"), key("c"), key("d")]
+        );
+        let pairs = client.batch_scan(ranges, 10).dedup().wait().unwrap();
+        assert_eq!(keys(pairs), vec![key("a"), key("b"), key("c"), key("d")]);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn delete_range_deletes_from_every_region() {
+        let client = Client::new_mock();
+        let mock = client.inner.mock.as_ref().unwrap();
+        mock.split(&[key("h"), key("q")], &[1, 2, 3]);
+        for k in &["a", "b", "i", "j", "r", "s"] {
+            client.put(key(k), *k).wait().unwrap();
+        }
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let reported = Arc::clone(&progress);
+        let delete_range = client
+            .delete_range(key("b")..)
+            .max_concurrency(2)
+            .on_progress(move |done, total| reported.lock().unwrap().push((done, total)));
+        mock.pause();
+        let mut delete_range = executor::spawn(delete_range);
+        assert_eq!(poll_once(&mut delete_range).unwrap(), Async::NotReady);
+        assert_eq!(mock.parked(), 2);
+        mock.resume();
+        assert_eq!(delete_range.wait_future().unwrap(), 5);
+        assert_eq!(*progress.lock().unwrap(), vec![(1, 3), (2, 3), (3, 3)]);
+        assert_eq!(mock.requests("RawDeleteRange"), vec![2, 3, 4]);
+        let left = client.scan(.., 10).wait().unwrap();
+        assert_eq!(left.into_pairs(), vec![KvPair::new(key("a"), "a".into())]);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn delete_range_limit_spans_regions() {
+        let client = Client::new_mock();
+        let mock = client.inner.mock.as_ref().unwrap();
+        mock.split(&[key("h"), key("q")], &[1, 2, 3]);
+        for k in &["a", "b", "i", "j", "r", "s"] {
+            client.put(key(k), *k).wait().unwrap();
+        }
+        assert_eq!(client.delete_range(..).limit(3).wait().unwrap(), 3);
+        assert_eq!(mock.requests("RawDeleteRange"), vec![2, 3]);
+        let left: Vec<Key> = client
+            .scan(.., 10)
+            .wait()
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(left, vec![key("j"), key("r"), key("s")]);
+    }
+
+    #[cfg(all(feature = "mock", feature = "metrics"))]
+    #[test]
+    fn request_latency_is_labeled_by_target_store() {
+        let client = Client::new_mock();
+        let mock = client.inner.mock.as_ref().unwrap();
+        // Store ids no other test uses, as the registry is shared.
+        mock.split(&[key("h")], &[76, 77]);
+        client.put(key("i"), "i").wait().unwrap();
+        client.get(key("i")).wait().unwrap();
+        client.get(key("j")).wait().unwrap();
+
+        let count = |cmd: &str, store: &str| -> u64 {
+            ::prometheus::gather()
+                .iter()
+                .filter(|family| family.get_name() == "tikv_client_store_request_duration_seconds")
+                .flat_map(|family| family.get_metric())
+                .filter(|metric| {
+                    let labels = metric.get_label();
+                    labels
+                        .iter()
+                        .any(|l| l.get_name() == "type" && l.get_value() == cmd)
+                        && labels
+                            .iter()
+                            .any(|l| l.get_name() == "store" && l.get_value() == store)
+                })
+                .map(|metric| metric.get_histogram().get_sample_count())
+                .sum()
+        };
+        assert_eq!(count("put", "77"), 1);
+        assert_eq!(count("get", "77"), 2);
+        assert_eq!(count("get", "76"), 0);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn range_admin_requests_target_every_overlapping_store() {
+        let client = Client::new_mock();
+        let mock = client.inner.mock.as_ref().unwrap();
+        mock.split(&[key("h"), key("q")], &[1, 2, 3]);
+        for k in &["a", "b", "i", "j", "r", "s"] {
+            client.put(key(k), *k).wait().unwrap();
+        }
+        client.compact_range(..).wait().unwrap();
+        assert_eq!(mock.requests("CompactRange"), vec![1, 2, 3]);
+
+        let whole = client.checksum(..).wait().unwrap();
+        assert_eq!(mock.requests("RawChecksum"), vec![2, 3, 4]);
+        assert_eq!(whole.total_kvs, 6);
+        let head = client.checksum(..key("i")).wait().unwrap();
+        let tail = client.checksum(key("i")..).wait().unwrap();
+        assert_eq!(head.merge(tail), whole);
+
+        client
+            .unsafe_destroy_range(key("i")..key("z"))
+            .wait()
+            .unwrap();
+        assert_eq!(mock.requests("UnsafeDestroyRange"), vec![2, 3]);
+        let left: Vec<Key> = client
+            .scan(.., 10)
+            .wait()
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(left, vec![key("a"), key("b")]);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn scan_walks_regions_in_order() {
+        use super::ScanItem::{Pair, RegionBoundary};
+
+        let client = Client::new_mock();
+        let mock = client.inner.mock.as_ref().unwrap();
+        mock.split(&[key("h"), key("q")], &[1, 2, 3]);
+        for k in &["a", "b", "i", "r", "s"] {
+            client.put(key(k), *k).wait().unwrap();
+        }
+        let pair = |k: &str| Pair(KvPair::new(key(k), k.into()));
+        let marked = client.scan(.., 10).with_region_markers().wait().unwrap();
+        let expected = vec![
+            RegionBoundary(2),
+            pair("a"),
+            pair("b"),
+            RegionBoundary(3),
+            pair("i"),
+            RegionBoundary(4),
+            pair("r"),
+            pair("s"),
+        ];
+        assert_eq!(marked, expected);
+        let marked = client.scan(.., 10).reverse().with_region_markers();
+        let expected = vec![
+            RegionBoundary(4),
+            pair("s"),
+            pair("r"),
+            RegionBoundary(3),
+            pair("i"),
+            RegionBoundary(2),
+            pair("b"),
+            pair("a"),
+        ];
+        assert_eq!(marked.wait().unwrap(), expected);
+        assert_eq!(mock.requests("RawScan"), vec![2, 3, 4, 4, 3, 2]);
+
+        // The limit carries over from one region to the next, and the regions past
+        // the range or the limit aren't asked.
+        let keys = |scan: Scan| -> Vec<Key> {
+            let pairs = scan.wait().unwrap().into_pairs();
+            pairs.into_iter().map(|pair| pair.into_inner().0).collect()
+        };
+        assert_eq!(keys(client.scan(.., 3)), vec![key("a"), key("b"), key("i")]);
+        let scan = client.scan(key("b")..key("r"), 10).reverse();
+        assert_eq!(keys(scan), vec![key("i"), key("b")]);
+        assert_eq!(mock.requests("RawScan")[6..], [2, 3, 4, 3, 2]);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn resumable_scan_round_trips_token() {
+        let client = Client::new_mock();
+        for k in &["a", "b", "c", "d", "pa", "pb", "pc"] {
+            client.put(key(k), *k).wait().unwrap();
+        }
+        let keys =
+            |pairs: ScanResult| -> Vec<Key> { pairs.into_iter().map(|(key, _)| key).collect() };
+        let resume = |token: ScanToken, limit| {
+            let token = ScanToken::from_bytes(&token.to_bytes()).unwrap();
+            client.scan_resume(token, limit).resumable().wait().unwrap()
+        };
+
+        let (first, token) = client.scan(.."e", 2).resumable().wait().unwrap();
+        assert_eq!(keys(first), vec![key("a"), key("b")]);
+        let (rest, token) = resume(token.unwrap(), 10);
+        assert_eq!(keys(rest), vec![key("c"), key("d")]);
+        assert!(token.is_none());
+
+        let scan = client.scan(.., 2).filter_prefix(key("p")).reverse();
+        let (first, token) = scan.resumable().wait().unwrap();
+        assert_eq!(keys(first), vec![key("pc"), key("pb")]);
+        let (rest, token) = resume(token.unwrap(), 2);
+        assert_eq!(keys(rest), vec![key("pa")]);
+        assert!(token.is_none());
+
+        let scan = client.scan(.., 3).take_while(|pair| pair.key() < &key("b"));
+        let (first, token) = scan.resumable().wait().unwrap();
+        assert_eq!(keys(first), vec![key("a")]);
+        assert!(token.is_none());
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn paginator_walks_range_in_pages() {
+        let client = Client::new_mock();
+        let mock = client.inner.mock.as_ref().unwrap();
+        mock.split(&[key("k0250"), key("k0625")], &[1, 2, 3]);
+        let all: Vec<Key> = (0..1000).map(|i| key(&format!("k{:04}", i))).collect();
+        client
+            .batch_put(all.iter().map(|k| (k.clone(), "v")))
+            .wait()
+            .unwrap();
+        client.put(key("l"), "v").wait().unwrap();
+
+        let mut paginator = client.paginate(key("k")..key("l"), 100);
+        let mut pages: Vec<Vec<Key>> = Vec::new();
+        loop {
+            let page = paginator.next_page().wait().unwrap();
+            if page.is_empty() {
+                break;
+            }
+            pages.push(page.into_iter().map(|pair| pair.into_inner().0).collect());
+        }
+        let expected: Vec<Vec<Key>> = all.chunks(100).map(|page| page.to_vec()).collect();
+        assert_eq!(pages, expected);
+        assert!(paginator.next_page().wait().unwrap().is_empty());
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn batch_get_chunks_by_count_and_key_bytes() {
+        let client = Client::new_mock_with(Config::default().max_message_size(5));
+        let keys = |keys: &[&str]| -> Vec<(usize, Key)> {
+            keys.iter().map(|k| key(k)).enumerate().collect()
+        };
+        let chunks = |batch_get: &BatchGet, keys| -> Vec<Vec<usize>> {
+            let chunks = batch_get.chunks(keys).unwrap();
+            chunks.into_iter().map(|chunk| chunk.positions).collect()
+        };
+        let batch_get = client.batch_get(Vec::<Key>::new()).chunk_size(2);
+        let one_byte = keys(&["a", "b", "c", "d", "e"]);
+        assert_eq!(
+            chunks(&batch_get, one_byte),
+            vec![vec![0, 1], vec![2, 3], vec![4]]
+        );
+        let batch_get = client.batch_get(Vec::<Key>::new());
+        let sized = keys(&["aa", "bb", "cc", "toolong", "d"]);
+        assert_eq!(
+            chunks(&batch_get, sized),
+            vec![vec![0, 1], vec![2], vec![3], vec![4]]
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn batch_get_keeps_chunks_in_flight() {
+        let client = Client::new_mock();
+        let mock = client.inner.mock.as_ref().unwrap();
+        mock.split(&[key("k25000")], &[1, 2]);
+        let keys: Vec<Key> = (0..50_000).map(|i| key(&format!("k{:05}", i))).collect();
+        let pairs: Vec<KvPair> = keys
+            .iter()
+            .map(|k| KvPair::new(k.clone(), "v".into()))
+            .collect();
+        mock.batch_put(&None, &pairs);
+        mock.pause();
+        let batch_get = client.batch_get(keys).max_concurrency(4);
+        let mut batch_get = executor::spawn(batch_get);
+        assert_eq!(poll_once(&mut batch_get).unwrap(), Async::NotReady);
+        assert_eq!(mock.parked(), 4);
+        mock.resume();
+        let found = batch_get.wait_future().unwrap();
+        assert_eq!(found.len(), 50_000);
+        // 25 chunks of at most 1024 keys per region.
+        let requests = mock.requests("RawBatchGet");
+        assert_eq!(requests.len(), 50);
+        assert_eq!(requests.iter().filter(|id| **id == 2).count(), 25);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn put_sink_keeps_failed_batches() {
+        let client = Client::new_mock();
+        let mock = client.inner.mock.as_ref().unwrap();
+        let mut sink = client.put_sink().max_pairs(2);
+        let pair = |k: &str| KvPair::new(key(k), k.into());
+        sink.start_send(pair("a")).unwrap();
+        mock.fail_next(Error::Timeout);
+        match sink.start_send(pair("b")) {
+            Err(Error::Timeout) => {}
+            result => panic!("unexpected {:?}", result),
+        }
+        mock.fail_next(Error::Timeout);
+        match sink.start_send(pair("c")) {
+            Err(Error::Timeout) => {}
+            result => panic!("unexpected {:?}", result),
+        }
+        assert!(client.get(key("a")).wait().unwrap().is_none());
+        sink.close().unwrap();
+        let pairs = client.scan(.., 10).wait().unwrap();
+        assert_eq!(pairs.len(), 3);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn batch_get_preserves_order_across_chunks_and_retries() {
+        let client = retrying_mock(3);
+        let mock = client.inner.mock.as_ref().unwrap();
+        for k in &["a", "b", "c", "e"] {
+            client.put(key(k), *k).wait().unwrap();
+        }
+        let keys: Vec<Key> = ["e", "a", "c", "x", "a", "b"]
+            .iter()
+            .map(|k| key(k))
+            .collect();
+        mock.fail_next(Error::NotLeader(1));
+        mock.fail_next(Error::StaleEpoch);
+        let values = client
+            .batch_get(keys.clone())
+            .chunk_size(2)
+            .preserve_order()
+            .wait()
+            .unwrap();
+        let expected: Vec<(Key, Option<Value>)> = keys
+            .into_iter()
+            .map(|k| {
+                let value = if k == key("x") {
+                    None
+                } else {
+                    Some(k.to_vec().into())
+                };
+                (k, value)
+            })
+            .collect();
+        assert_eq!(values, expected);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn only_default_gets_coalesce() {
+        let client = Client::new_mock_with(Config::default().coalesce_reads(true));
+        assert!(client.get(key("a")).coalesces());
+        assert!(!client.get(key("a")).priority(Priority::High).coalesces());
+        let follower = client.get(key("a")).read_policy(ReadPolicy::Follower);
+        assert!(!follower.coalesces());
+        let deadline = Instant::now() + Duration::from_secs(1);
+        assert!(!client.get(key("a")).deadline(deadline).coalesces());
+        assert!(!client
+            .get(key("a"))
+            .stale(Duration::from_secs(1))
+            .coalesces());
+        assert!(!Client::new_mock().get(key("a")).coalesces());
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn reporting_delete_resolves_to_prior_existence() {
+        let client = Client::new_mock();
+        client.put(key("a"), "1").wait().unwrap();
+        assert!(client.delete(key("a")).report_existence().wait().unwrap());
+        assert!(!client.delete(key("a")).report_existence().wait().unwrap());
+        client.put(key("b"), "2").wait().unwrap();
+        let () = client.delete(key("b")).wait().unwrap();
+        assert_eq!(client.get(key("b")).wait().unwrap(), None);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn keys_over_max_key_size_are_rejected() {
+        let client = Client::new_mock();
+        let at_limit: Key = vec![b'k'; 4096].into();
+        client.put(at_limit.clone(), "v").wait().unwrap();
+        assert!(client.get(at_limit.clone()).wait().unwrap().is_some());
+        client.delete(at_limit).wait().unwrap();
+
+        let client = Client::new_mock_with(Config::default().max_key_size(8));
+        client.put(key("12345678"), "v").wait().unwrap();
+        let over = || key("123456789");
+        let results = vec![
+            client.get(over()).wait().map(|_| ()),
+            client.put(over(), "v").wait(),
+            client.delete(over()).wait(),
+        ];
+        for result in results {
+            match result {
+                Err(Error::KeyTooLong { len: 9, max: 8 }) => {}
+                result => panic!("unexpected {:?}", result),
+            }
+        }
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn clones_serve_gets_from_many_threads() {
+        let client = Client::new_mock();
+        let keys: Vec<Key> = (0..16).map(|i| key(&format!("k{:02}", i))).collect();
+        for k in &keys {
+            client.put(k.clone(), k.to_vec()).wait().unwrap();
+        }
+        let threads: Vec<_> = keys
+            .into_iter()
+            .map(|k| {
+                let client = client.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        let value = client.get(k.clone()).wait().unwrap().unwrap();
+                        assert_eq!(value.to_vec(), k.to_vec());
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(Arc::strong_count(&client.inner), 1);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn close_fails_later_requests() {
+        let client = Client::new_mock();
+        let clone = client.clone();
+        let get = clone.get(key("a"));
+        client.close().wait().unwrap();
+        for result in [get.wait(), clone.get(key("a")).wait()] {
+            match result {
+                Err(Error::Closed) => {}
+                result => panic!("unexpected {:?}", result),
+            }
+        }
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn get_stream_yields_slices_of_the_value() {
+        let client = Client::new_mock();
+        let value: Vec<u8> = (0..150u8).collect();
+        client.put(key("a"), value.clone()).wait().unwrap();
+        let chunks = client
+            .get_stream(key("a"))
+            .chunk_size(64)
+            .collect()
+            .wait()
+            .unwrap();
+        let lens: Vec<usize> = chunks.iter().map(|chunk| chunk.len()).collect();
+        assert_eq!(lens, vec![64, 64, 22]);
+        assert_eq!(chunks.concat(), value);
+        // The chunks share the buffer the value was read into.
+        assert_eq!(chunks[1].as_ptr(), chunks[0][64..].as_ptr());
+        assert!(client
+            .get_stream(key("b"))
+            .collect()
+            .wait()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn close_waits_for_requests_in_flight() {
+        let client = Client::new_mock();
+        let mock = Arc::clone(client.inner.mock.as_ref().unwrap());
+        client.put(key("a"), "a").wait().unwrap();
+        let clone = client.clone();
+        mock.pause();
+        let mut get = executor::spawn(clone.get(key("a")));
+        assert_eq!(poll_once(&mut get).unwrap(), Async::NotReady);
+        let mut close = executor::spawn(client.close());
+        assert_eq!(poll_once(&mut close).unwrap(), Async::NotReady);
+        mock.resume();
+        assert_eq!(get.wait_future().unwrap(), Some("a".into()));
+        assert_eq!(poll_once(&mut close).unwrap(), Async::NotReady);
+        drop(get);
+        assert_eq!(poll_once(&mut close).unwrap(), Async::Ready(()));
+        match clone.get(key("a")).wait() {
+            Err(Error::Closed) => {}
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn max_bytes_stops_scan() {
+        let client = Client::new_mock();
+        for k in &["a", "b", "c", "d"] {
+            client.put(key(k), "xyz").wait().unwrap();
+        }
+        let (pairs, truncated) = client.scan(.., 10).max_bytes(8).wait().unwrap();
+        assert_eq!(pairs.len(), 2);
+        assert!(truncated);
+        let (pairs, truncated) = client.scan(.., 10).max_bytes(12).wait().unwrap();
+        assert_eq!(pairs.len(), 4);
+        assert!(!truncated);
+        let (pairs, truncated) = client.scan(.., 3).max_bytes(12).wait().unwrap();
+        assert_eq!(pairs.len(), 3);
+        assert!(!truncated);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn mock_rejects_unsupported_requests() {
+        let client = Client::new_mock();
+        let unsupported = |result: Result<(), Error>| match result {
+            Err(Error::MockUnsupported(_)) => {}
+            result => panic!("unexpected {:?}", result),
+        };
+        unsupported(client.ingest(SstFile::new("a.sst", ..)).wait());
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn requests_past_their_deadline_time_out() {
+        let timed_out = |result: Result<(), Error>| match result {
+            Err(Error::Timeout) => {}
+            result => panic!("unexpected {:?}", result),
+        };
+        let client = Client::new_mock();
+        let past = Instant::now();
+        timed_out(client.get(key("a")).deadline(past).wait().map(|_| ()));
+        timed_out(client.put(key("a"), "a").deadline(past).wait());
+        timed_out(client.delete(key("a")).deadline(past).wait().map(|_| ()));
+        timed_out(client.scan(.., 10).deadline(past).wait().map(|_| ()));
+        let mock = client.inner.mock.as_ref().unwrap();
+        assert!(mock.requests("RawPut").is_empty());
+
+        // A pending request times out at `Config::timeout` if it's earlier.
+        let config = Config::default().timeout(Duration::from_millis(20));
+        let client = Client::new_mock_in(Arc::clone(mock), config).unwrap();
+        mock.pause();
+        let later = Instant::now() + Duration::from_secs(60);
+        timed_out(client.get(key("a")).deadline(later).wait().map(|_| ()));
+    }
+
+    #[test]
+    fn column_family_converts_only_known_names() {
+        use super::{ColumnFamily, Error};
+        use std::convert::TryFrom;
+
+        assert_eq!(ColumnFamily::try_from("lock").unwrap(), ColumnFamily::LOCK);
+        assert_eq!(
+            "default".parse::<ColumnFamily>().unwrap(),
+            ColumnFamily::DEFAULT
+        );
+        match ColumnFamily::try_from("locks") {
+            Err(Error::UnknownColumnFamily(ref name)) if name == "locks" => {}
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn column_family_deserializes_known_names() {
+        use super::ColumnFamily;
+
+        #[derive(Deserialize)]
+        struct Options {
+            cf: ColumnFamily,
+        }
+        let options: Options = ::toml::from_str("cf = \"write\"").unwrap();
+        assert_eq!(options.cf, ColumnFamily::WRITE);
+        assert!(::toml::from_str::<Options>("cf = \"writes\"").is_err());
+    }
+
+    #[cfg(feature = "mock")]
+    fn retrying_mock(max_attempts: u32) -> Client {
+        Client::new_mock_with(Config::default().retry(max_attempts, Backoff::NoJitter))
+    }
+
+    #[cfg(feature = "mock")]
+    struct NoNotify;
+
+    #[cfg(feature = "mock")]
+    impl Notify for NoNotify {
+        fn notify(&self, _: usize) {}
+    }
+
+    // Polls `future` once, from a task that nothing wakes, for the tests that look at
+    // what is pending in between polls.
+    #[cfg(feature = "mock")]
+    fn poll_once<F: Future>(future: &mut Spawn<F>) -> Poll<F::Item, F::Error> {
+        future.poll_future_notify(&Arc::new(NoNotify), 0)
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn mock_reports_its_region_layout() {
+        let client = Client::new_mock();
+        let mock = client.inner.mock.as_ref().unwrap();
+        assert_eq!(client.regions_in_range(..).wait().unwrap().len(), 1);
+        mock.split(&[key("h"), key("p")], &[1, 2, 1]);
+        let regions = client.regions_in_range(key("b")..key("k")).wait().unwrap();
+        let bounds: Vec<(Key, Key)> = regions
+            .into_iter()
+            .map(|region| (region.start_key, region.end_key))
+            .collect();
+        assert_eq!(bounds, vec![range("", "h"), range("h", "p")]);
+        let region = client.get_region(key("q")).wait().unwrap();
+        assert_eq!((region.start_key, region.end_key), range("p", ""));
+        assert_eq!(region.leader.unwrap().store_id, 1);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn requests_follow_region_splits() {
+        let client = retrying_mock(2);
+        let mock = client.inner.mock.as_ref().unwrap();
+        client.put(key("a"), "1").wait().unwrap();
+        mock.split(&[key("h")], &[1, 2]);
+        assert_eq!(client.get(key("a")).wait().unwrap(), Some("1".into()));
+        assert_eq!(client.get(key("q")).wait().unwrap(), None);
+        assert_eq!(mock.requests("RawPut"), vec![1]);
+        // The first `RawGet` was routed with the stale region and resent.
+        assert_eq!(mock.requests("RawGet"), vec![1, 2, 3]);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn api_v2_round_trips_through_encoded_keys() {
+        let client = Client::new_mock_with(Config::default().api_version(ApiVersion::V2));
+        let mock = client.inner.mock.as_ref().unwrap();
+        // Written without the V2 prefix, so outside of the client's keyspace.
+        mock.put(&None, key("c"), "v1".into());
+        client.put(key("a"), "1").wait().unwrap();
+        client.batch_put(vec![(key("b"), "2")]).wait().unwrap();
+        let stored: Vec<Key> = mock
+            .scan(&None, &range("", ""), 10, false, None)
+            .into_iter()
+            .map(|pair| pair.into_inner().0)
+            .collect();
+        assert_eq!(stored, vec![key("c"), key("r\0\0\0a"), key("r\0\0\0b")]);
+
+        assert_eq!(client.get(key("a")).wait().unwrap(), Some("1".into()));
+        assert!(client.get(key("c")).wait().unwrap().is_none());
+        let keys = |pairs: Vec<KvPair>| -> Vec<Key> {
+            pairs.into_iter().map(|pair| pair.into_inner().0).collect()
+        };
+        let pairs = client.batch_get(vec![key("a"), key("b"), key("c")]);
+        let mut found = keys(pairs.wait().unwrap());
+        found.sort();
+        assert_eq!(found, vec![key("a"), key("b")]);
+        let scanned = client.scan(.., 10).reverse().wait().unwrap();
+        assert_eq!(keys(scanned.into_pairs()), vec![key("b"), key("a")]);
+        assert_eq!(
+            client.scan_keys(key("b").., 10).wait().unwrap(),
+            vec![key("b")]
+        );
+        client.delete(key("a")).wait().unwrap();
+        assert_eq!(client.delete_range(..).wait().unwrap(), 1);
+        assert_eq!(mock.scan(&None, &range("", ""), 10, false, None).len(), 1);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn keyspaces_do_not_see_each_others_keys() {
+        let mock = Arc::new(MockStore::default());
+        let first_id = mock.create_keyspace("first");
+        let second_id = mock.create_keyspace("second");
+        let first = Client::new_mock_in(Arc::clone(&mock), Config::default().keyspace("first"));
+        let first = first.unwrap();
+        let second = Client::new_mock_in(Arc::clone(&mock), Config::default().keyspace("second"));
+        let second = second.unwrap();
+        assert_eq!(first.keyspace_id(), Some(first_id));
+        assert_eq!(second.keyspace_id(), Some(second_id));
+
+        first.put(key("k"), "1").wait().unwrap();
+        second.put(key("l"), "2").wait().unwrap();
+        assert_eq!(first.get(key("k")).wait().unwrap(), Some("1".into()));
+        assert!(second.get(key("k")).wait().unwrap().is_none());
+        let keys = |client: &Client| -> Vec<Key> {
+            let pairs = client.scan(.., 10).wait().unwrap().into_pairs();
+            pairs.into_iter().map(|pair| pair.into_inner().0).collect()
+        };
+        assert_eq!(keys(&first), vec![key("k")]);
+        assert_eq!(keys(&second), vec![key("l")]);
+        assert_eq!(second.delete_range(..).wait().unwrap(), 1);
+        assert_eq!(keys(&first), vec![key("k")]);
+
+        match Client::new_mock_in(mock, Config::default().keyspace("third")) {
+            Err(Error::KeyspaceNotFound(name)) => assert_eq!(name, "third"),
+            Err(err) => panic!("unexpected error {:?}", err),
+            Ok(_) => panic!("unknown keyspace accepted"),
+        }
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn paused_mock_holds_requests_back() {
+        let client = Client::new_mock();
+        let mock = client.inner.mock.as_ref().unwrap();
+        mock.pause();
+        let mut put = executor::spawn(client.put(key("a"), "1"));
+        assert_eq!(poll_once(&mut put).unwrap(), Async::NotReady);
+        assert_eq!(mock.parked(), 1);
+        assert!(mock.requests("RawPut").is_empty());
+        mock.resume();
+        assert_eq!(poll_once(&mut put).unwrap(), Async::Ready(()));
+        assert_eq!(mock.requests("RawPut"), vec![1]);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn batch_put_groups_pairs_by_region() {
+        let client = retrying_mock(3);
+        let mock = client.inner.mock.as_ref().unwrap();
+        mock.split(&[key("h"), key("q")], &[1, 2, 3]);
+        let pairs = vec![
+            (key("a"), "1"),
+            (key("i"), "2"),
+            (key("b"), "3"),
+            (key("z"), "4"),
+        ];
+        client.batch_put(pairs.clone()).wait().unwrap();
+        assert_eq!(mock.requests("RawBatchPut"), vec![2, 3, 4]);
+        for (key, value) in pairs {
+            assert_eq!(client.get(key).wait().unwrap(), Some(value.into()));
+        }
+
+        // Each group's region error drops the region of that group's keys.
+        mock.split(&[key("h"), key("q")], &[1, 2, 3]);
+        mock.fail_next(Error::StaleEpoch);
+        mock.fail_next(Error::StaleEpoch);
+        client
+            .batch_put(vec![(key("c"), "5"), (key("r"), "6")])
+            .wait()
+            .unwrap();
+        assert_eq!(mock.requests("RawBatchPut")[3..], [2, 4, 5, 7]);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn batch_put_keeps_last_pair_of_each_key() {
+        let client = Client::new_mock();
+        let pairs = vec![(key("a"), "1"), (key("b"), "2"), (key("a"), "3")];
+        client.batch_put(pairs).wait().unwrap();
+        assert_eq!(client.get(key("a")).wait().unwrap(), Some("3".into()));
+        assert_eq!(client.get(key("b")).wait().unwrap(), Some("2".into()));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn batch_put_caps_requests_in_flight() {
+        let client = Client::new_mock();
+        let mock = client.inner.mock.as_ref().unwrap();
+        mock.split(&[key("h"), key("q")], &[1, 2, 3]);
+        mock.pause();
+        let pairs = vec![(key("a"), "1"), (key("i"), "2"), (key("z"), "3")];
+        let mut batch_put = executor::spawn(client.batch_put(pairs).max_concurrency(2));
+        assert_eq!(poll_once(&mut batch_put).unwrap(), Async::NotReady);
+        assert_eq!(mock.parked(), 2);
+        mock.resume();
+        assert_eq!(poll_once(&mut batch_put).unwrap(), Async::Ready(()));
+        assert_eq!(mock.requests("RawBatchPut"), vec![2, 3, 4]);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn retries_region_errors() {
+        let client = retrying_mock(3);
+        let mock = client.inner.mock.as_ref().unwrap();
+        mock.fail_next(Error::NotLeader(1));
+        mock.fail_next(Error::StaleEpoch);
+        client.put(key("a"), "1").wait().unwrap();
+        mock.fail_next(Error::RegionNotFound(1));
+        assert_eq!(client.get(key("a")).wait().unwrap(), Some("1".into()));
+        mock.fail_next(Error::NotLeader(1));
+        let pairs = client.batch_get(vec![key("a"), key("b")]).wait().unwrap();
+        assert_eq!(pairs.len(), 1);
+        mock.fail_next(Error::NotLeader(1));
+        assert_eq!(client.scan(key("a").., 10).wait().unwrap().len(), 1);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn surfaces_region_error_once_attempts_run_out() {
+        let client = retrying_mock(2);
+        let mock = client.inner.mock.as_ref().unwrap();
+        mock.fail_next(Error::NotLeader(1));
+        mock.fail_next(Error::NotLeader(2));
+        match client.delete(key("a")).wait() {
+            Err(Error::NotLeader(2)) => {}
+            result => panic!("unexpected {:?}", result),
+        }
+        mock.fail_next(Error::Timeout);
+        match client.put(key("a"), "1").wait() {
+            Err(Error::Timeout) => {}
+            result => panic!("unexpected {:?}", result),
+        }
+        assert!(client.get(key("a")).wait().unwrap().is_none());
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn request_retry_skips_non_idempotent_writes() {
+        let config = Config::default().request_retry(2, Backoff::NoJitter);
+        let client = Client::new_mock_with(config);
+        let mock = client.inner.mock.as_ref().unwrap();
+        mock.fail_next(Error::Timeout);
+        client.put(key("a"), "1").wait().unwrap();
+        mock.fail_next(Error::Timeout);
+        match client.put(key("a"), "2").idempotent(false).wait() {
+            Err(Error::Timeout) => {}
+            result => panic!("unexpected {:?}", result),
+        }
+        assert_eq!(client.get(key("a")).wait().unwrap(), Some("1".into()));
+    }
 }
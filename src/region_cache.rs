@@ -0,0 +1,213 @@
+// Copyright 2018 The TiKV Project Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Consulted and updated by the request futures as they route keys and handle
+// region errors.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use {Error, Key, Peer, Region, RegionChange};
+
+pub type RegionChangeListener = Box<Fn(RegionChange) + Send + Sync>;
+
+/// Routing information for the regions seen so far, keyed by start key.
+#[derive(Default)]
+pub struct RegionCache {
+    regions: BTreeMap<Key, CachedRegion>,
+    // See `Config::region_cache_ttl`.
+    ttl: Option<Duration>,
+    // The leaders of regions dropped after a `NotLeader` or `StaleEpoch`, reported
+    // to the listeners along with the new leader once the region is cached again.
+    stale_leaders: HashMap<u64, Option<Peer>>,
+    listeners: Vec<RegionChangeListener>,
+}
+
+struct CachedRegion {
+    region: Region,
+    cached_at: Instant,
+}
+
+impl RegionCache {
+    /// A zero `ttl` means entries never expire.
+    pub fn new(ttl: Option<Duration>) -> Self {
+        RegionCache {
+            ttl: ttl.filter(|ttl| *ttl > Duration::from_secs(0)),
+            ..RegionCache::default()
+        }
+    }
+
+    /// The cached region containing `key`, or `None` if it has to be resolved from
+    /// PD, including when it was cached longer than the TTL ago.
+    pub fn get(&self, key: &Key) -> Option<&Region> {
+        self.lookup(key)
+            .filter(|cached| self.is_fresh(cached))
+            .map(|cached| &cached.region)
+    }
+
+    /// Like `get`, for the region containing the keys right below `key`, where a
+    /// reverse scan up to `key` starts. An empty `key` stands for the end of the
+    /// keyspace.
+    pub fn get_before(&self, key: &Key) -> Option<&Region> {
+        let cached = if key.is_empty() {
+            self.regions.values().next_back()
+        } else {
+            self.regions
+                .range(..key.clone())
+                .next_back()
+                .map(|(_, cached)| cached)
+        };
+        cached
+            .filter(|cached| {
+                let end_key = &cached.region.end_key;
+                end_key.is_empty() || (!key.is_empty() && end_key >= key)
+            })
+            .filter(|cached| self.is_fresh(cached))
+            .map(|cached| &cached.region)
+    }
+
+    fn is_fresh(&self, cached: &CachedRegion) -> bool {
+        self.ttl.is_none_or(|ttl| cached.cached_at.elapsed() < ttl)
+    }
+
+    fn lookup(&self, key: &Key) -> Option<&CachedRegion> {
+        self.regions
+            .range(..=key.clone())
+            .next_back()
+            .map(|(_, cached)| cached)
+            .filter(|cached| cached.region.contains(key))
+    }
+
+    pub fn add_listener(&mut self, listener: RegionChangeListener) {
+        self.listeners.push(listener);
+    }
+
+    /// Caches `region`, evicting any cached region it overlaps.
+    pub fn insert(&mut self, region: Region) {
+        if let Some(old_leader) = self.stale_leaders.remove(&region.id) {
+            let change = RegionChange {
+                region_id: region.id,
+                old_leader,
+                new_leader: region.leader.clone(),
+            };
+            for listener in &self.listeners {
+                listener(change.clone());
+            }
+        }
+        self.regions.retain(|_, cached| {
+            let cached = &cached.region;
+            let starts_after = !region.end_key.is_empty() && cached.start_key >= region.end_key;
+            let ends_before = !cached.end_key.is_empty() && cached.end_key <= region.start_key;
+            starts_after || ends_before
+        });
+        self.regions.insert(
+            region.start_key.clone(),
+            CachedRegion {
+                region,
+                cached_at: Instant::now(),
+            },
+        );
+    }
+
+    pub fn invalidate(&mut self, region_id: u64) {
+        self.regions
+            .retain(|_, cached| cached.region.id != region_id);
+    }
+
+    fn invalidate_leader(&mut self, region_id: u64) {
+        let old_leader = self
+            .regions
+            .values()
+            .find(|cached| cached.region.id == region_id)
+            .and_then(|cached| cached.region.leader.clone());
+        self.stale_leaders.insert(region_id, old_leader);
+        self.invalidate(region_id);
+    }
+
+    /// Drops the routing made stale by `err`, returning whether the request that
+    /// failed with it should be retried after re-resolving its region from PD.
+    pub fn on_region_error(&mut self, key: &Key, err: &Error) -> bool {
+        match *err {
+            Error::NotLeader(region_id) => {
+                self.invalidate_leader(region_id);
+                true
+            }
+            Error::RegionNotFound(region_id) => {
+                self.invalidate(region_id);
+                true
+            }
+            Error::StaleEpoch => {
+                let region_id = self.lookup(key).map(|cached| cached.region.id);
+                if let Some(region_id) = region_id {
+                    self.invalidate_leader(region_id);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::RegionCache;
+    use {Key, Region};
+
+    fn key(s: &str) -> Key {
+        s.as_bytes().to_vec().into()
+    }
+
+    fn region(id: u64, start: &str, end: &str) -> Region {
+        Region {
+            id,
+            start_key: key(start),
+            end_key: key(end),
+            ..Region::default()
+        }
+    }
+
+    // Makes every cached entry look as if it was cached `age` ago.
+    fn age(cache: &mut RegionCache, age: Duration) {
+        let cached_at = Instant::now() - age;
+        for cached in cache.regions.values_mut() {
+            cached.cached_at = cached_at;
+        }
+    }
+
+    #[test]
+    fn entries_older_than_ttl_are_resolved_again() {
+        let mut cache = RegionCache::new(Some(Duration::from_secs(10)));
+        cache.insert(region(1, "", "m"));
+        let key = key("a");
+        age(&mut cache, Duration::from_secs(5));
+        assert_eq!(cache.get(&key).map(|region| region.id), Some(1));
+        age(&mut cache, Duration::from_secs(11));
+        assert!(cache.get(&key).is_none());
+        // Resolving the region again refreshes the entry.
+        cache.insert(region(2, "", "m"));
+        assert_eq!(cache.get(&key).map(|region| region.id), Some(2));
+    }
+
+    #[test]
+    fn zero_ttl_never_expires() {
+        for ttl in [None, Some(Duration::from_secs(0))] {
+            let mut cache = RegionCache::new(ttl);
+            cache.insert(region(1, "", ""));
+            age(&mut cache, Duration::from_secs(3600));
+            let key = key("a");
+            assert_eq!(cache.get(&key).map(|region| region.id), Some(1));
+        }
+    }
+}
@@ -0,0 +1,331 @@
+// Copyright 2018 The TiKV Project Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// The retry loops of `Connect` and of the request futures.
+
+use std::sync::RwLock;
+use std::time::Duration;
+
+use futures::{Async, Future, Poll};
+
+use region_cache::RegionCache;
+use timer::Delay;
+use {Backoff, Config, Error, Key, RetryPolicy};
+
+// The policy for region errors when `Config::retry` isn't set.
+const DEFAULT_REGION_RETRY: RetryPolicy = RetryPolicy {
+    max_attempts: 10,
+    backoff: Backoff::FullJitter {
+        base: Duration::from_millis(2),
+        max: Duration::from_millis(500),
+    },
+};
+
+/// The attempts of one request. A request that fails with a region error drops the
+/// stale routing from the region cache and is sent again, to the region resolved
+/// anew, up to the attempts of `Config::retry`. Other errors that are
+/// `Error::is_retryable` resend an idempotent request up to the attempts of
+/// `Config::request_retry`; each policy counts its own attempts.
+///
+/// Each request counts its own attempts and draws its own backoff, so concurrent
+/// requests failing together don't retry in lockstep.
+pub struct Retry {
+    region_policy: RetryPolicy,
+    region_attempts: u32,
+    request_policy: Option<RetryPolicy>,
+    request_attempts: u32,
+    delay: Option<Delay>,
+}
+
+impl Retry {
+    pub fn new(config: &Config) -> Self {
+        Retry {
+            region_policy: config.retry.unwrap_or(DEFAULT_REGION_RETRY),
+            region_attempts: 0,
+            request_policy: config.request_retry,
+            request_attempts: 0,
+            delay: None,
+        }
+    }
+
+    /// Waits out the backoff before the next attempt, if any.
+    pub fn poll_delay(&mut self) -> Poll<(), Error> {
+        if let Some(ref mut delay) = self.delay {
+            if let Async::NotReady = delay.poll()? {
+                return Ok(Async::NotReady);
+            }
+        }
+        self.delay = None;
+        Ok(Async::Ready(()))
+    }
+
+    /// Handles `err`, the failure of an attempt concerning `key`: returns `Ok` if the
+    /// request should be sent again once `poll_delay` is ready, and `err` otherwise.
+    /// Only region errors, which the store rejects before applying anything, are
+    /// retried if the request isn't `idempotent`.
+    pub fn on_error(
+        &mut self,
+        region_cache: &RwLock<RegionCache>,
+        key: &Key,
+        err: Error,
+        idempotent: bool,
+    ) -> Result<(), Error> {
+        if region_cache.write().unwrap().on_region_error(key, &err) {
+            self.region_attempts += 1;
+            if self.region_attempts < self.region_policy.max_attempts {
+                let backoff = self.region_policy.backoff;
+                self.delay = Some(Delay::new(backoff.delay(self.region_attempts - 1)));
+                return Ok(());
+            }
+        } else if let Some(policy) = self.request_policy {
+            if idempotent && err.is_retryable() {
+                self.request_attempts += 1;
+                if self.request_attempts < policy.max_attempts {
+                    let delay = policy.backoff.delay(self.request_attempts - 1);
+                    self.delay = Some(Delay::new(delay));
+                    return Ok(());
+                }
+            }
+        }
+        Err(err)
+    }
+}
+
+/// Runs `handshake` against each endpoint in turn until one succeeds, as described
+/// on `Config::retry`. Without a policy, every endpoint is tried once.
+pub struct Failover<H, F> {
+    endpoints: Vec<String>,
+    max_attempts: u32,
+    backoff: Backoff,
+    handshake: H,
+    attempts: u32,
+    current: Option<F>,
+    delay: Option<Delay>,
+}
+
+impl<H, F> Failover<H, F>
+where
+    H: FnMut(&str) -> F,
+    F: Future<Error = Error>,
+{
+    pub fn new(endpoints: Vec<String>, policy: Option<RetryPolicy>, handshake: H) -> Self {
+        let (max_attempts, backoff) = match policy {
+            Some(policy) => (policy.max_attempts, policy.backoff),
+            None => (endpoints.len() as u32, Backoff::NoJitter),
+        };
+        Failover {
+            endpoints,
+            max_attempts,
+            backoff,
+            handshake,
+            attempts: 0,
+            current: None,
+            delay: None,
+        }
+    }
+}
+
+impl<H, F> Future for Failover<H, F>
+where
+    H: FnMut(&str) -> F,
+    F: Future<Error = Error>,
+{
+    type Item = F::Item;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            if let Some(ref mut delay) = self.delay {
+                if let Async::NotReady = delay.poll()? {
+                    return Ok(Async::NotReady);
+                }
+            }
+            self.delay = None;
+            if self.current.is_none() {
+                if self.endpoints.is_empty() || self.attempts >= self.max_attempts {
+                    return Err(Error::ConnectFailed(self.endpoints.clone()));
+                }
+                let endpoint = &self.endpoints[self.attempts as usize % self.endpoints.len()];
+                self.current = Some((self.handshake)(endpoint));
+            }
+            match self.current.as_mut().unwrap().poll() {
+                Ok(Async::Ready(item)) => return Ok(Async::Ready(item)),
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(ref err) if err.is_retryable() => {
+                    self.current = None;
+                    self.delay = Some(Delay::new(self.backoff.delay(self.attempts)));
+                    self.attempts += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::RwLock;
+    use std::time::Duration;
+
+    use futures::future::{self, FutureResult};
+    use futures::Future;
+
+    use super::{Failover, Retry};
+    use region_cache::RegionCache;
+    use {Backoff, Config, Error, Key, RetryPolicy};
+
+    fn unavailable() -> Error {
+        let status = ::grpc::RpcStatus::new(::grpc::RpcStatusCode::Unavailable, None);
+        Error::Grpc(::grpc::Error::RpcFailure(status))
+    }
+
+    // A handshake that only succeeds against `live`, recording the endpoints tried.
+    fn handshake<'a>(
+        live: &'a str,
+        tried: &'a mut Vec<String>,
+    ) -> impl FnMut(&str) -> FutureResult<String, Error> + 'a {
+        move |endpoint| {
+            tried.push(endpoint.to_owned());
+            if endpoint == live {
+                future::ok(endpoint.to_owned())
+            } else {
+                future::err(unavailable())
+            }
+        }
+    }
+
+    fn endpoints() -> Vec<String> {
+        vec!["dead:2379".to_owned(), "live:2379".to_owned()]
+    }
+
+    #[test]
+    fn fails_over_to_live_endpoint() {
+        let mut tried = Vec::new();
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            backoff: Backoff::Exponential {
+                base: Duration::from_millis(1),
+                max: Duration::from_millis(10),
+            },
+        };
+        let connected = Failover::new(
+            endpoints(),
+            Some(policy),
+            handshake("live:2379", &mut tried),
+        )
+        .wait()
+        .unwrap();
+        assert_eq!(connected, "live:2379");
+        assert_eq!(tried, endpoints());
+    }
+
+    #[test]
+    fn cycles_through_endpoints_until_attempts_run_out() {
+        let mut tried = Vec::new();
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            backoff: Backoff::NoJitter,
+        };
+        match Failover::new(endpoints(), Some(policy), handshake("none", &mut tried)).wait() {
+            Err(Error::ConnectFailed(ref failed)) if *failed == endpoints() => {}
+            result => panic!("unexpected {:?}", result),
+        }
+        assert_eq!(tried, vec!["dead:2379", "live:2379", "dead:2379"]);
+    }
+
+    #[test]
+    fn tries_each_endpoint_once_by_default() {
+        let mut tried = Vec::new();
+        assert!(
+            Failover::new(endpoints(), None, handshake("none", &mut tried))
+                .wait()
+                .is_err()
+        );
+        assert_eq!(tried, endpoints());
+    }
+
+    // Feeds `errors` to a fresh `Retry`, returning how many of them it retried.
+    fn retried(config: &Config, errors: Vec<Error>, idempotent: bool) -> usize {
+        let region_cache = RwLock::new(RegionCache::new(None));
+        let mut retry = Retry::new(config);
+        let mut retried = 0;
+        for err in errors {
+            future::poll_fn(|| retry.poll_delay()).wait().unwrap();
+            if retry
+                .on_error(&region_cache, &Key::default(), err, idempotent)
+                .is_err()
+            {
+                break;
+            }
+            retried += 1;
+        }
+        retried
+    }
+
+    #[test]
+    fn request_retry_resends_retryable_errors() {
+        let config = Config::default().request_retry(3, Backoff::NoJitter);
+        let errors = || vec![Error::Timeout, unavailable(), Error::Timeout];
+        assert_eq!(retried(&config, errors(), true), 2);
+        assert_eq!(retried(&Config::default(), errors(), true), 0);
+        assert_eq!(retried(&config, errors(), false), 0);
+        assert_eq!(
+            retried(&config, vec![Error::KeyTooLong { len: 1, max: 0 }], true),
+            0
+        );
+    }
+
+    #[test]
+    fn region_errors_have_their_own_attempts() {
+        let config = Config::default()
+            .retry(2, Backoff::NoJitter)
+            .request_retry(2, Backoff::NoJitter);
+        let errors = vec![
+            Error::NotLeader(1),
+            Error::Timeout,
+            Error::StaleEpoch,
+            Error::Timeout,
+        ];
+        assert_eq!(retried(&config, errors, true), 2);
+        let errors = vec![Error::NotLeader(1), Error::Timeout];
+        assert_eq!(retried(&config, errors, false), 1);
+    }
+
+    #[test]
+    fn request_retry_waits_for_backoff() {
+        let backoff = Backoff::Exponential {
+            base: Duration::from_millis(20),
+            max: Duration::from_millis(20),
+        };
+        let config = Config::default().request_retry(2, backoff);
+        let region_cache = RwLock::new(RegionCache::new(None));
+        let mut retry = Retry::new(&config);
+        retry
+            .on_error(&region_cache, &Key::default(), Error::Timeout, true)
+            .unwrap();
+        let started = ::std::time::Instant::now();
+        future::poll_fn(|| retry.poll_delay()).wait().unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn surfaces_permanent_errors() {
+        let failover = Failover::new(endpoints(), None, |_: &str| {
+            future::err::<(), _>(Error::Security("bad certificate".to_owned()))
+        });
+        match failover.wait() {
+            Err(Error::Security(_)) => {}
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,87 @@
+// Copyright 2018 The TiKV Project Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use grpc::{
+    Channel, ChannelBuilder, ChannelCredentialsBuilder, CompressionAlgorithms, Environment,
+};
+
+use {Compression, Config, Error, Result};
+
+pub fn channel_builder(env: Arc<Environment>, config: &Config) -> ChannelBuilder {
+    let mut cb = ChannelBuilder::new(env);
+    if let Some(interval) = config.keepalive_interval {
+        cb = cb.keepalive_time(interval);
+    }
+    if let Some(timeout) = config.keepalive_timeout {
+        cb = cb.keepalive_timeout(timeout);
+    }
+    if let Some(size) = config.max_message_size {
+        let size = cmp::min(size, i32::MAX as usize) as i32;
+        cb = cb.max_receive_message_len(size).max_send_message_len(size);
+    }
+    if let Compression::Gzip = config.compression {
+        cb = cb.default_compression_algorithm(CompressionAlgorithms::Gzip);
+    }
+    cb
+}
+
+fn load_pem(pem: &Option<Vec<u8>>, path: &Option<PathBuf>) -> Result<Option<Vec<u8>>> {
+    if let Some(ref pem) = *pem {
+        return Ok(Some(pem.clone()));
+    }
+    match *path {
+        Some(ref path) => fs::read(path)
+            .map(Some)
+            .map_err(|e| Error::Security(format!("failed to load {}: {}", path.display(), e))),
+        None => Ok(None),
+    }
+}
+
+#[derive(Default)]
+pub struct SecurityManager {
+    ca: Vec<u8>,
+    cert: Vec<u8>,
+    key: Vec<u8>,
+}
+
+impl SecurityManager {
+    pub fn load(config: &Config) -> Result<SecurityManager> {
+        let ca = load_pem(&config.ca_pem, &config.ca_path)?;
+        let cert = load_pem(&config.cert_pem, &config.cert_path)?;
+        let key = load_pem(&config.key_pem, &config.key_path)?;
+        match (ca, cert, key) {
+            (None, None, None) => Ok(SecurityManager::default()),
+            (Some(ca), Some(cert), Some(key)) => Ok(SecurityManager { ca, cert, key }),
+            _ => Err(Error::Security(
+                "CA, certificate and key must be set together".to_owned(),
+            )),
+        }
+    }
+
+    pub fn connect(&self, cb: ChannelBuilder, addr: &str) -> Channel {
+        if self.ca.is_empty() {
+            cb.connect(addr)
+        } else {
+            let cred = ChannelCredentialsBuilder::new()
+                .root_cert(self.ca.clone())
+                .cert(self.cert.clone(), self.key.clone())
+                .build();
+            cb.secure_connect(addr, cred)
+        }
+    }
+}
@@ -0,0 +1,190 @@
+// Copyright 2018 The TiKV Project Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// The crate has no event loop of its own, so a pending `Delay` parks a thread that
+// wakes its task once the delay has elapsed. Delays are only used for timeouts and
+// backoffs, which are few, so the threads are not pooled.
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures::task::{self, Task};
+use futures::{Async, Future, Poll};
+
+use Error;
+
+/// A future that resolves once `deadline` has passed.
+pub struct Delay {
+    deadline: Instant,
+    // The task to wake, shared with the waiting thread once it has been started.
+    task: Option<Arc<Mutex<Task>>>,
+}
+
+impl Delay {
+    pub fn new(duration: Duration) -> Self {
+        Delay::until(Instant::now() + duration)
+    }
+
+    pub fn until(deadline: Instant) -> Self {
+        Delay {
+            deadline,
+            task: None,
+        }
+    }
+}
+
+impl Future for Delay {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let now = Instant::now();
+        if now >= self.deadline {
+            return Ok(Async::Ready(()));
+        }
+        match self.task {
+            Some(ref waiting) => *waiting.lock().unwrap() = task::current(),
+            None => {
+                let waiting = Arc::new(Mutex::new(task::current()));
+                let wait = self.deadline - now;
+                let task = Arc::clone(&waiting);
+                thread::spawn(move || {
+                    thread::sleep(wait);
+                    task.lock().unwrap().notify();
+                });
+                self.task = Some(waiting);
+            }
+        }
+        Ok(Async::NotReady)
+    }
+}
+
+/// Bounds polled work by `deadline`, or not at all if there is no deadline. Unlike
+/// `Timeout`, it doesn't own the work, which the owner polls in between `check` and
+/// `poll`.
+pub struct Deadline {
+    deadline: Option<Instant>,
+    // Started the first time the work is pending.
+    delay: Option<Delay>,
+}
+
+impl Deadline {
+    pub fn new(deadline: Option<Instant>) -> Self {
+        Deadline {
+            deadline,
+            delay: None,
+        }
+    }
+
+    /// Fails with `Error::Timeout` if the deadline has passed, so that work isn't
+    /// started or resumed late.
+    pub fn check(&self) -> Result<(), Error> {
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(Error::Timeout),
+            _ => Ok(()),
+        }
+    }
+
+    /// Passes on `result`, the outcome of polling the work, unless it is pending and
+    /// the deadline has passed, in which case it fails with `Error::Timeout`. Otherwise
+    /// the current task is woken at the deadline.
+    pub fn poll<T>(&mut self, result: Poll<T, Error>) -> Poll<T, Error> {
+        if let Ok(Async::NotReady) = result {
+            if let Some(deadline) = self.deadline {
+                let delay = self.delay.get_or_insert_with(|| Delay::until(deadline));
+                if let Async::Ready(()) = delay.poll()? {
+                    return Err(Error::Timeout);
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Fails `future` with `Error::Timeout` if it hasn't resolved within `timeout`, or
+/// never if there is no timeout.
+pub struct Timeout<F> {
+    future: F,
+    deadline: Deadline,
+}
+
+impl<F> Timeout<F> {
+    pub fn new(future: F, timeout: Option<Duration>) -> Self {
+        Timeout {
+            future,
+            deadline: Deadline::new(timeout.map(|timeout| Instant::now() + timeout)),
+        }
+    }
+}
+
+impl<F: Future<Error = Error>> Future for Timeout<F> {
+    type Item = F::Item;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let result = self.future.poll();
+        self.deadline.poll(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use futures::{future, Async, Future};
+
+    use super::{Deadline, Delay, Timeout};
+    use Error;
+
+    #[test]
+    fn delay_waits() {
+        let started = Instant::now();
+        Delay::new(Duration::from_millis(20)).wait().unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn timeout_fails_pending_future() {
+        let timeout = Timeout::new(
+            future::empty::<(), Error>(),
+            Some(Duration::from_millis(20)),
+        );
+        match timeout.wait() {
+            Err(Error::Timeout) => {}
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+
+    #[test]
+    fn deadline_checks_before_and_fails_pending_work() {
+        let past = Deadline::new(Some(Instant::now()));
+        assert!(past.check().is_err());
+        let mut deadline = Deadline::new(Some(Instant::now() + Duration::from_millis(20)));
+        assert!(deadline.check().is_ok());
+        let pending = future::poll_fn(|| deadline.poll(Ok::<_, Error>(Async::NotReady::<()>)));
+        match pending.wait() {
+            Err(Error::Timeout) => {}
+            result => panic!("unexpected {:?}", result),
+        }
+        assert!(Deadline::new(None).check().is_ok());
+    }
+
+    #[test]
+    fn timeout_passes_result_through() {
+        let timeout = Timeout::new(future::ok::<_, Error>(1), Some(Duration::from_secs(60)));
+        assert_eq!(timeout.wait().unwrap(), 1);
+        let timeout = Timeout::new(future::ok::<_, Error>(2), None);
+        assert_eq!(timeout.wait().unwrap(), 2);
+    }
+}
@@ -0,0 +1,39 @@
+// Copyright 2018 The TiKV Project Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Opened by the request futures through `Instrument` when they first poll.
+
+use tracing::field;
+use tracing::{Level, Span};
+
+/// Opens the span covering one request. The target region and store are filled in
+/// by `record_region` and `record_store` once the request has been routed.
+pub fn request_span(op: &'static str, key_len: usize, cf: &str) -> Span {
+    span!(
+        Level::DEBUG,
+        "tikv_request",
+        op,
+        key_len,
+        cf,
+        region_id = field::Empty,
+        store = field::Empty
+    )
+}
+
+pub fn record_region(span: &Span, region_id: u64) {
+    span.record("region_id", region_id);
+}
+
+pub fn record_store(span: &Span, store: &str) {
+    span.record("store", store);
+}
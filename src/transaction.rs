@@ -11,13 +11,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::ops::RangeBounds;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::iter::Peekable;
+use std::ops::{Bound, RangeBounds};
+use std::vec;
 
-use futures::{Future, Poll, Stream};
+use futures::{Async, Future, Poll, Stream};
 
+use security::SecurityManager;
 use {Config, Error, Key, KvPair, Value};
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
 pub struct Timestamp(u64);
 
 impl Into<Timestamp> for u64 {
@@ -38,19 +43,101 @@ impl Timestamp {
     pub fn logical(self) -> i64 {
         (self.0 & 0xFFFF as u64) as i64
     }
+
+    pub fn into_version(self) -> u64 {
+        self.0
+    }
 }
 
-pub struct Scanner;
+/// The pairs of a range in a snapshot, overridden by the mutations buffered in the
+/// transaction, if any, for the keys they cover.
+pub struct Scanner {
+    snapshot: Box<Stream<Item = KvPair, Error = Error> + Send>,
+    // The next pair of `snapshot`, held back until the buffered mutations before it
+    // have been yielded.
+    next_snapshot: Option<KvPair>,
+    snapshot_done: bool,
+    buffered: Peekable<vec::IntoIter<(Key, Mutation)>>,
+    reverse: bool,
+}
+
+impl Scanner {
+    // `buffered` must be in scan order.
+    fn new(
+        snapshot: Box<Stream<Item = KvPair, Error = Error> + Send>,
+        buffered: Vec<(Key, Mutation)>,
+        reverse: bool,
+    ) -> Self {
+        Scanner {
+            snapshot,
+            next_snapshot: None,
+            snapshot_done: false,
+            buffered: buffered.into_iter().peekable(),
+            reverse,
+        }
+    }
+}
 
 impl Stream for Scanner {
     type Item = KvPair;
     type Error = Error;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if self.next_snapshot.is_none() && !self.snapshot_done {
+                match self.snapshot.poll()? {
+                    Async::Ready(Some(pair)) => self.next_snapshot = Some(pair),
+                    Async::Ready(None) => self.snapshot_done = true,
+                    Async::NotReady => return Ok(Async::NotReady),
+                }
+            }
+            let order = match (self.buffered.peek(), self.next_snapshot.as_ref()) {
+                (None, None) => return Ok(Async::Ready(None)),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(&(ref key, _)), Some(pair)) if self.reverse => pair.key().cmp(key),
+                (Some(&(ref key, _)), Some(pair)) => key.cmp(pair.key()),
+            };
+            if order != Ordering::Less {
+                let pair = self.next_snapshot.take();
+                if order == Ordering::Greater {
+                    return Ok(Async::Ready(pair));
+                }
+            }
+            // A buffered mutation comes first, or replaces the snapshot pair of the
+            // same key, which was just dropped.
+            if let Some((key, Mutation::Put(value))) = self.buffered.next() {
+                return Ok(Async::Ready(Some(KvPair::new(key, value))));
+            }
+        }
+    }
+}
+
+// The pairs of a range as of the snapshot's timestamp.
+struct SnapshotScan {
+    range: (Bound<Key>, Bound<Key>),
+    reverse: bool,
+}
+
+impl Stream for SnapshotScan {
+    type Item = KvPair;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let _range = &self.range;
+        let _reverse = self.reverse;
         unimplemented!()
     }
 }
 
+fn to_owned_bound(bound: Bound<&Key>) -> Bound<Key> {
+    match bound {
+        Bound::Included(key) => Bound::Included(key.clone()),
+        Bound::Excluded(key) => Bound::Excluded(key.clone()),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub enum IsolationLevel {
     SnapshotIsolation,
@@ -59,11 +146,17 @@ pub enum IsolationLevel {
 
 pub struct Get {
     key: Key,
+    // The mutation of `key` buffered in the transaction, which is read instead of
+    // the snapshot.
+    buffered: Option<Mutation>,
 }
 
 impl Get {
     fn new(key: Key) -> Self {
-        Get { key }
+        Get {
+            key,
+            buffered: None,
+        }
     }
 }
 
@@ -72,26 +165,41 @@ impl Future for Get {
     type Error = Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.buffered.take() {
+            Some(Mutation::Put(value)) => return Ok(Async::Ready(value)),
+            Some(Mutation::Delete) => return Ok(Async::Ready(Value::default())),
+            None => {}
+        }
         let _key = &self.key;
         unimplemented!()
     }
 }
 
 pub struct BatchGet {
+    // The keys to read from the snapshot.
     keys: Vec<Key>,
+    // The pairs of the keys written in the transaction, which are not read from the
+    // snapshot.
+    buffered: Vec<KvPair>,
 }
 
 impl BatchGet {
     fn new(keys: Vec<Key>) -> Self {
-        BatchGet { keys }
+        BatchGet {
+            keys,
+            buffered: Vec::new(),
+        }
     }
 }
 
 impl Future for BatchGet {
-    type Item = Value;
+    type Item = Vec<KvPair>;
     type Error = Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if self.keys.is_empty() {
+            return Ok(Async::Ready(self.buffered.drain(..).collect()));
+        }
         let _keys = &self.keys;
         unimplemented!()
     }
@@ -112,7 +220,10 @@ impl Future for Commit {
     type Error = Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        let _txn = &self.txn;
+        if self.txn.is_readonly() {
+            return Ok(Async::Ready(()));
+        }
+        let _mutations = &self.txn.mutations;
         unimplemented!()
     }
 }
@@ -157,14 +268,11 @@ impl Future for LockKeys {
     }
 }
 
-pub struct Set {
-    key: Key,
-    value: Value,
-}
+pub struct Set;
 
 impl Set {
-    fn new(key: Key, value: Value) -> Self {
-        Set { key, value }
+    fn new() -> Self {
+        Set
     }
 }
 
@@ -173,19 +281,15 @@ impl Future for Set {
     type Error = Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        let _key = &self.key;
-        let _value = &self.value;
-        unimplemented!()
+        Ok(Async::Ready(()))
     }
 }
 
-pub struct Delete {
-    key: Key,
-}
+pub struct Delete;
 
 impl Delete {
-    fn new(key: Key) -> Self {
-        Delete { key }
+    fn new() -> Self {
+        Delete
     }
 }
 
@@ -194,13 +298,24 @@ impl Future for Delete {
     type Error = Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        let _key = &self.key;
-        unimplemented!()
+        Ok(Async::Ready(()))
     }
 }
 
+#[derive(Clone, Eq, PartialEq, Debug)]
+enum Mutation {
+    Put(Value),
+    Delete,
+}
+
+/// A percolator-style transaction.
+///
+/// `set` and `delete` only buffer the mutation locally, and `get`, `batch_get` and
+/// the scans of the transaction see the buffered mutations over its snapshot.
+/// Committing a transaction without mutations resolves immediately.
 pub struct Transaction {
     snapshot: Snapshot,
+    mutations: BTreeMap<Key, Mutation>,
 }
 
 impl Transaction {
@@ -217,7 +332,7 @@ impl Transaction {
     }
 
     pub fn is_readonly(&self) -> bool {
-        unimplemented!()
+        self.mutations.is_empty()
     }
 
     pub fn start_ts(&self) -> Timestamp {
@@ -233,27 +348,62 @@ impl Transaction {
     }
 
     pub fn get(&self, key: impl AsRef<Key>) -> Get {
-        self.snapshot.get(key)
+        let mut get = self.snapshot.get(key);
+        get.buffered = self.mutations.get(&get.key).cloned();
+        get
     }
 
     pub fn batch_get(&self, keys: impl AsRef<[Key]>) -> BatchGet {
-        self.snapshot.batch_get(keys)
+        let (buffered, unbuffered): (Vec<Key>, Vec<Key>) = keys
+            .as_ref()
+            .iter()
+            .cloned()
+            .partition(|key| self.mutations.contains_key(key));
+        let mut batch_get = self.snapshot.batch_get(unbuffered);
+        batch_get.buffered = buffered
+            .into_iter()
+            .filter_map(|key| match self.mutations[&key] {
+                Mutation::Put(ref value) => Some(KvPair::new(key, value.clone())),
+                Mutation::Delete => None,
+            })
+            .collect();
+        batch_get
     }
 
     pub fn scan(&self, range: impl RangeBounds<Key>) -> Scanner {
-        self.snapshot.scan(range)
+        let buffered = self.buffered_in(&range);
+        let mut scanner = self.snapshot.scan(range);
+        scanner.buffered = buffered.into_iter().peekable();
+        scanner
     }
 
     pub fn scan_reverse(&self, range: impl RangeBounds<Key>) -> Scanner {
-        self.snapshot.scan_reverse(range)
+        let mut buffered = self.buffered_in(&range);
+        buffered.reverse();
+        let mut scanner = self.snapshot.scan_reverse(range);
+        scanner.buffered = buffered.into_iter().peekable();
+        scanner
+    }
+
+    // The buffered mutations of the keys in `range`, in ascending key order.
+    fn buffered_in(&self, range: &impl RangeBounds<Key>) -> Vec<(Key, Mutation)> {
+        self.mutations
+            .iter()
+            .filter(|(key, _)| range.contains(*key))
+            .map(|(key, mutation)| (key.clone(), mutation.clone()))
+            .collect()
     }
 
     pub fn set(&mut self, key: impl Into<Key>, value: impl Into<Value>) -> Set {
-        Set::new(key.into(), value.into())
+        self.mutations
+            .insert(key.into(), Mutation::Put(value.into()));
+        Set::new()
     }
 
     pub fn delete(&mut self, key: impl AsRef<Key>) -> Delete {
-        Delete::new(key.as_ref().clone())
+        self.mutations
+            .insert(key.as_ref().clone(), Mutation::Delete);
+        Delete::new()
     }
 }
 
@@ -264,18 +414,29 @@ impl Snapshot {
         Get::new(key.as_ref().clone())
     }
 
+    /// Gets the values of `keys`. Keys that don't exist are omitted from the result.
     pub fn batch_get(&self, keys: impl AsRef<[Key]>) -> BatchGet {
         BatchGet::new(keys.as_ref().to_vec().clone())
     }
 
     pub fn scan(&self, range: impl RangeBounds<Key>) -> Scanner {
-        drop(range);
-        unimplemented!()
+        self.scan_impl(range, false)
     }
 
     pub fn scan_reverse(&self, range: impl RangeBounds<Key>) -> Scanner {
-        drop(range);
-        unimplemented!()
+        self.scan_impl(range, true)
+    }
+
+    fn scan_impl(&self, range: impl RangeBounds<Key>, reverse: bool) -> Scanner {
+        let range = (
+            to_owned_bound(range.start_bound()),
+            to_owned_bound(range.end_bound()),
+        );
+        Scanner::new(
+            Box::new(SnapshotScan { range, reverse }),
+            Vec::new(),
+            reverse,
+        )
     }
 }
 
@@ -294,7 +455,7 @@ impl Future for Connect {
     type Error = Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        let _config = &self.config;
+        let _security = SecurityManager::load(&self.config)?;
         unimplemented!()
     }
 }
@@ -323,3 +484,77 @@ impl Client {
         unimplemented!()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use futures::{stream, Future, Stream};
+
+    use super::{Mutation, Scanner, Snapshot, Transaction};
+    use {Key, KvPair, Value};
+
+    fn key(s: &str) -> Key {
+        s.as_bytes().to_vec().into()
+    }
+
+    fn value(s: &str) -> Value {
+        s.into()
+    }
+
+    fn pair(k: &str, v: &str) -> KvPair {
+        KvPair::new(key(k), value(v))
+    }
+
+    fn transaction() -> Transaction {
+        Transaction {
+            snapshot: Snapshot,
+            mutations: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn reads_own_writes() {
+        let mut txn = transaction();
+        assert!(txn.is_readonly());
+        txn.set(key("a"), "1");
+        txn.set(key("b"), "2");
+        txn.delete(key("b"));
+        assert!(!txn.is_readonly());
+        assert_eq!(txn.get(key("a")).wait().unwrap(), value("1"));
+        assert_eq!(txn.get(key("b")).wait().unwrap(), Value::default());
+        let pairs = txn.batch_get(vec![key("a"), key("b")]).wait().unwrap();
+        assert_eq!(pairs, vec![pair("a", "1")]);
+    }
+
+    #[test]
+    fn scan_merges_buffered_mutations() {
+        let buffered = vec![
+            (key("b"), Mutation::Put(value("20"))),
+            (key("c"), Mutation::Delete),
+            (key("d"), Mutation::Put(value("4"))),
+        ];
+        let snapshot = vec![pair("a", "1"), pair("b", "2"), pair("c", "3")];
+
+        let scanner = Scanner::new(
+            Box::new(stream::iter_ok(snapshot.clone())),
+            buffered.clone(),
+            false,
+        );
+        let pairs = scanner.collect().wait().unwrap();
+        assert_eq!(pairs, vec![pair("a", "1"), pair("b", "20"), pair("d", "4")]);
+
+        let scanner = Scanner::new(
+            Box::new(stream::iter_ok(snapshot.into_iter().rev())),
+            buffered.into_iter().rev().collect(),
+            true,
+        );
+        let pairs = scanner.collect().wait().unwrap();
+        assert_eq!(pairs, vec![pair("d", "4"), pair("b", "20"), pair("a", "1")]);
+    }
+
+    #[test]
+    fn readonly_commit_resolves() {
+        transaction().commit().wait().unwrap();
+    }
+}